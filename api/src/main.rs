@@ -4,6 +4,8 @@ use std::io;
 use routes::auth::config as auth_config;
 use routes::order::config as order_config;
 use routes::depth::config as depth_config;
+use routes::admin::config as admin_config;
+use routes::trades::config as trades_config;
 use db::establish_connection_pool;
 
 mod routes;
@@ -24,7 +26,7 @@ async fn main() -> io::Result<()> {
         // Configure CORS
         let cors = Cors::default()
             .allowed_origin("http://localhost:3000")  // Your frontend URL
-            .allowed_methods(vec!["GET", "POST"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
             .allowed_headers(vec![
                 header::AUTHORIZATION,
                 header::ACCEPT,
@@ -43,6 +45,8 @@ async fn main() -> io::Result<()> {
                     .configure(auth_config)
                     .configure(order_config)
                     .configure(depth_config)
+                    .configure(admin_config)
+                    .configure(trades_config)
             )
     })
     .bind(("0.0.0.0", 8080))?
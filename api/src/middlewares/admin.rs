@@ -0,0 +1,68 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use std::future::{ready, Ready};
+use actix_web::{dev, dev::Service, dev::Transform, Error, HttpResponse, web::Data, HttpMessage};
+use futures_util::future::LocalBoxFuture;
+use db::DbPool;
+use crate::utils::web_utils::get_user_from_jwt;
+
+/// Like `AuthMiddleware`, but only lets admin users through.
+pub struct AdminMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for AdminMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+    dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let pool = req.app_data::<Data<DbPool>>().expect("DB Pool not found");
+
+        let response = match get_user_from_jwt(&req, pool) {
+            Some(user) if user.is_admin => {
+                req.extensions_mut().insert(user.id.to_string());
+                let res = self.service.call(req);
+                return Box::pin(async move { res.await.map(ServiceResponse::map_into_left_body) });
+            }
+            Some(_) => HttpResponse::Forbidden().json(serde_json::json!({ "error": "Forbidden" })),
+            None => HttpResponse::Unauthorized().json(serde_json::json!({ "error": "Unauthorized" })),
+        };
+
+        let (request, _pl) = req.into_parts();
+        let response = response.map_into_right_body();
+        Box::pin(async { Ok(ServiceResponse::new(request, response)) })
+    }
+}
+
+#[derive(Clone)]
+pub struct AdminService;
+
+impl AdminService {
+    pub fn new() -> Self {
+        AdminService
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AdminService
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AdminMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AdminMiddleware { service }))
+    }
+}
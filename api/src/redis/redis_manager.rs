@@ -18,6 +18,7 @@ static INSTANCE: Lazy<Mutex<RedisManager>> = Lazy::new(|| {
     Mutex::new(RedisManager::new())
 });
 
+#[derive(Clone)]
 pub struct RedisManager {
     client: Client,
     publisher: Client,
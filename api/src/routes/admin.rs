@@ -0,0 +1,80 @@
+use actix_web::{web, Responder, HttpResponse};
+use crate::redis::redis_manager::RedisManager;
+use crate::types::redis::{MessageToEngine, MessageFromOrderbook, Asset, MarketConfig, SetMarketStatusData};
+use crate::middlewares::admin::AdminService;
+use log::info;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .wrap(AdminService::new())
+            .route("/assets", web::post().to(list_asset))
+            .route("/markets", web::post().to(list_market))
+            .route("/markets/{market}/status", web::put().to(set_market_status))
+    );
+}
+
+/// Adds an asset to the registry; markets can only trade listed assets.
+pub async fn list_asset(
+    user_id: web::ReqData<String>,
+    body: web::Json<Asset>,
+) -> impl Responder {
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
+    let asset = body.into_inner();
+    info!("Admin {} listing asset {}", user_id.as_str(), asset.symbol);
+
+    let message = MessageToEngine::ListAsset {
+        data: asset,
+    };
+
+    match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::BadRequest().json(response),
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Lists a new market, or replaces the rules and status of a listed one.
+/// Spec fields left out fall back to the engine's permissive defaults.
+pub async fn list_market(
+    user_id: web::ReqData<String>,
+    body: web::Json<MarketConfig>,
+) -> impl Responder {
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
+    let market = body.into_inner();
+    info!("Admin {} listing market {}", user_id.as_str(), market.symbol);
+
+    let message = MessageToEngine::ListMarket {
+        data: Box::new(market),
+    };
+
+    match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::BadRequest().json(response),
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Halts, resumes, or switches a market to cancel-only or delisted.
+pub async fn set_market_status(
+    user_id: web::ReqData<String>,
+    market: web::Path<String>,
+    body: web::Json<SetMarketStatusData>,
+) -> impl Responder {
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
+    let market = market.into_inner();
+    info!("Admin {} setting {} to {:?}", user_id.as_str(), market, body.status);
+
+    let message = MessageToEngine::SetMarketStatus {
+        data: SetMarketStatusData {
+            market,
+            status: body.status,
+        },
+    };
+
+    match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::NotFound().json(response),
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
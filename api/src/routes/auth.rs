@@ -106,6 +106,7 @@ async fn signup(
         username: form.username.clone(),
         created_at: Utc::now().naive_utc(),
         updated_at: Utc::now().naive_utc(),
+        is_admin: false,
     };
 
     diesel::insert_into(users)
@@ -25,7 +25,7 @@ pub async fn get_depth(
 ) -> impl Responder {
     let market = path.into_inner().market;
     info!("Getting depth for market: {:?}", market);
-    let redis_manager = RedisManager::get_instance().lock().unwrap();
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
 
     let message = MessageToEngine::GetDepth {
         data: GetDepthData {
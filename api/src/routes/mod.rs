@@ -1,3 +1,5 @@
 pub mod auth;
 pub mod order;
-pub mod depth;
\ No newline at end of file
+pub mod depth;
+pub mod admin;
+pub mod trades;
\ No newline at end of file
@@ -1,7 +1,12 @@
 use actix_web::{web, Responder, HttpResponse};
 use crate::redis::redis_manager::RedisManager;
-use crate::types::redis::{MessageToEngine, GetOpenOrdersData, CancelOrderData, CreateOrderData};
+use crate::types::redis::{MessageToEngine, MessageFromOrderbook, GetOpenOrdersData, CancelOrderData, CancelAllOrdersData, GetOrderData, CreateOrderData, AmendOrderData, DeadManSwitchData, OrderSide};
 use crate::middlewares::auth::AuthService;
+use db::DbPool;
+use db::models::{OrderStatusRecord, OrderStatusChange};
+use db::schema::{order_statuses, order_status_history};
+use diesel::prelude::*;
+use serde_json::json;
 use log::info;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
@@ -9,7 +14,13 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web::scope("/orders")
             .wrap(AuthService::new())
             .route("/open", web::get().to(get_open_orders))
+            .route("", web::delete().to(cancel_all_orders))
+            .route("/dead-man-switch", web::post().to(set_dead_man_switch))
+            .route("/client/{client_order_id}", web::get().to(get_order_by_client_id))
+            .route("/client/{client_order_id}", web::delete().to(cancel_order_by_client_id))
+            .route("/{order_id}", web::get().to(get_order_status))
             .route("/{order_id}", web::delete().to(cancel_order))
+            .route("/{order_id}", web::patch().to(amend_order))
             .route("/", web::post().to(create_order))
     );
 }
@@ -23,7 +34,7 @@ pub async fn get_open_orders(
     user_id: web::ReqData<String>,
     query: web::Query<OpenOrdersQuery>,
 ) -> impl Responder {
-    let redis_manager = RedisManager::get_instance().lock().unwrap();
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
     
     let message = MessageToEngine::GetOpenOrders {
         data: GetOpenOrdersData {
@@ -37,20 +48,189 @@ pub async fn get_open_orders(
     }
 }
 
+#[derive(serde::Serialize)]
+pub struct OrderStatusResponse {
+    #[serde(flatten)]
+    order: OrderStatusRecord,
+    history: Vec<OrderStatusChange>,
+}
+
+/// Current status, cumulative fills and timestamps of one of the caller's
+/// orders, open or closed, with every status change it went through.
+pub async fn get_order_status(
+    pool: web::Data<DbPool>,
+    user_id: web::ReqData<String>,
+    order_id: web::Path<String>,
+) -> impl Responder {
+    let conn = &mut pool.get().expect("couldn't get db connection from pool");
+    let order_id = order_id.into_inner();
+
+    let order = match order_statuses::table
+        .filter(order_statuses::order_id.eq(&order_id))
+        .filter(order_statuses::user_id.eq(user_id.as_str()))
+        .select(OrderStatusRecord::as_select())
+        .first(conn)
+    {
+        Ok(order) => order,
+        Err(diesel::result::Error::NotFound) => {
+            return HttpResponse::NotFound().json(json!({ "error": "Order not found" }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match order_status_history::table
+        .filter(order_status_history::order_id.eq(&order_id))
+        .order((order_status_history::changed_at, order_status_history::id))
+        .select(OrderStatusChange::as_select())
+        .load(conn)
+    {
+        Ok(history) => HttpResponse::Ok().json(OrderStatusResponse { order, history }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CancelOrderQuery {
+    market: String,
+}
+
 pub async fn cancel_order(
     user_id: web::ReqData<String>,
-    query: web::Query<CancelOrderData>,
+    order_id: web::Path<String>,
+    query: web::Query<CancelOrderQuery>,
 ) -> impl Responder {
-    let redis_manager = RedisManager::get_instance().lock().unwrap();
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
 
     let message = MessageToEngine::CancelOrder {
         data: CancelOrderData{
-            order_id: query.order_id.clone(),
+            order_id: Some(order_id.into_inner()),
+            client_order_id: None,
+            market: query.market.clone(),
+        },
+    };
+
+    match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::NotFound().json(response),
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Looks up one of the caller's open orders by the client order id it was
+/// placed with.
+pub async fn get_order_by_client_id(
+    user_id: web::ReqData<String>,
+    client_order_id: web::Path<String>,
+    query: web::Query<CancelOrderQuery>,
+) -> impl Responder {
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
+
+    let message = MessageToEngine::GetOrder {
+        data: GetOrderData {
+            order_id: None,
+            client_order_id: Some(client_order_id.into_inner()),
+            market: query.market.clone(),
+        },
+    };
+
+    match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::NotFound().json(response),
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+pub async fn cancel_order_by_client_id(
+    user_id: web::ReqData<String>,
+    client_order_id: web::Path<String>,
+    query: web::Query<CancelOrderQuery>,
+) -> impl Responder {
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
+
+    let message = MessageToEngine::CancelOrder {
+        data: CancelOrderData {
+            order_id: None,
+            client_order_id: Some(client_order_id.into_inner()),
             market: query.market.clone(),
         },
     };
 
     match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::NotFound().json(response),
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CancelAllOrdersQuery {
+    market: Option<String>,
+    side: Option<OrderSide>,
+}
+
+/// Cancels all of the caller's open orders, optionally only in one market
+/// and/or on one side, and returns the ids of the cancelled orders.
+pub async fn cancel_all_orders(
+    user_id: web::ReqData<String>,
+    query: web::Query<CancelAllOrdersQuery>,
+) -> impl Responder {
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
+    let query = query.into_inner();
+
+    let message = MessageToEngine::CancelAllOrders {
+        data: CancelAllOrdersData {
+            market: query.market,
+            side: query.side,
+        },
+    };
+
+    match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::BadRequest().json(response),
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Arms or refreshes the caller's dead man's switch: unless called again
+/// within `timeout_ms`, all of their orders are cancelled. A zero timeout
+/// disarms it.
+pub async fn set_dead_man_switch(
+    user_id: web::ReqData<String>,
+    body: web::Json<DeadManSwitchData>,
+) -> impl Responder {
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
+
+    let message = MessageToEngine::SetDeadManSwitch {
+        data: body.into_inner(),
+    };
+
+    match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Changes the price and/or quantity of a resting order in place instead of
+/// cancelling and re-creating it.
+pub async fn amend_order(
+    user_id: web::ReqData<String>,
+    order_id: web::Path<String>,
+    body: web::Json<AmendOrderData>,
+) -> impl Responder {
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
+    let request_body = body.into_inner();
+
+    let message = MessageToEngine::AmendOrder {
+        data: AmendOrderData {
+            order_id: order_id.into_inner(),
+            market: request_body.market,
+            price: request_body.price,
+            quantity: request_body.quantity,
+        },
+    };
+
+    match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::BadRequest().json(response),
         Ok(response) => HttpResponse::Ok().json(response),
         Err(_) => HttpResponse::InternalServerError().finish()
     }
@@ -60,7 +240,7 @@ pub async fn create_order(
     user_id: web::ReqData<String>,
     body: web::Json<CreateOrderData>,
 ) -> impl Responder {
-    let redis_manager = RedisManager::get_instance().lock().unwrap();
+    let redis_manager = RedisManager::get_instance().lock().unwrap().clone();
     let request_body = body.into_inner();
 
     let message = MessageToEngine::CreateOrder {
@@ -69,10 +249,19 @@ pub async fn create_order(
             price: request_body.price,
             quantity: request_body.quantity,
             side: request_body.side,
+            order_type: request_body.order_type,
+            time_in_force: request_body.time_in_force,
+            post_only: request_body.post_only,
+            stop_price: request_body.stop_price,
+            display_quantity: request_body.display_quantity,
+            self_trade_prevention: request_body.self_trade_prevention,
+            expires_at: request_body.expires_at,
+            client_order_id: request_body.client_order_id,
         },
     };
 
     match redis_manager.send_and_await(message, user_id.into_inner()).await {
+        Ok(response @ MessageFromOrderbook::OrderRejected { .. }) => HttpResponse::BadRequest().json(response),
         Ok(response) => HttpResponse::Ok().json(response),
         Err(_) => {
             info!("Failed to create order");
@@ -0,0 +1,94 @@
+use actix_web::{web, Responder, HttpResponse};
+use crate::middlewares::auth::AuthService;
+use db::DbPool;
+use db::models::Trade;
+use db::schema::trades;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use chrono::NaiveDateTime;
+
+const DEFAULT_HISTORY_LIMIT: i64 = 100;
+const MAX_HISTORY_LIMIT: i64 = 1000;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/trades")
+            .wrap(AuthService::new())
+            .route("/history", web::get().to(get_trade_history))
+    );
+}
+
+#[derive(Deserialize)]
+pub struct TradeHistoryQuery {
+    market: Option<String>,
+    limit: Option<i64>,
+}
+
+/// One trade as seen by one of the users in it.
+#[derive(Serialize)]
+pub struct UserTrade {
+    trade_id: i64,
+    market: String,
+    side: &'static str,
+    is_maker: bool,
+    price: String,
+    quantity: String,
+    quote_quantity: String,
+    fee: String,
+    fee_asset: String,
+    timestamp: NaiveDateTime,
+}
+
+impl UserTrade {
+    fn new(trade: Trade, user_id: &str) -> Self {
+        let is_buyer = trade.buyer_id == user_id;
+        let (side, is_maker, fee, fee_asset) = if is_buyer {
+            ("buy", trade.is_buyer_maker, trade.buyer_fee, trade.buyer_fee_asset)
+        } else {
+            ("sell", !trade.is_buyer_maker, trade.seller_fee, trade.seller_fee_asset)
+        };
+        Self {
+            trade_id: trade.trade_id,
+            market: trade.market,
+            side,
+            is_maker,
+            price: trade.price,
+            quantity: trade.quantity,
+            quote_quantity: trade.quote_quantity,
+            fee,
+            fee_asset,
+            timestamp: trade.timestamp,
+        }
+    }
+}
+
+/// The caller's most recent trades, newest first, with the fee they paid
+/// on each.
+pub async fn get_trade_history(
+    pool: web::Data<DbPool>,
+    user_id: web::ReqData<String>,
+    query: web::Query<TradeHistoryQuery>,
+) -> impl Responder {
+    let conn = &mut pool.get().expect("couldn't get db connection from pool");
+    let user_id = user_id.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+
+    let mut history = trades::table
+        .filter(trades::buyer_id.eq(&user_id).or(trades::seller_id.eq(&user_id)))
+        .into_boxed();
+    if let Some(market) = &query.market {
+        history = history.filter(trades::market.eq(market));
+    }
+
+    match history
+        .order((trades::timestamp.desc(), trades::trade_id.desc()))
+        .limit(limit)
+        .select(Trade::as_select())
+        .load(conn)
+    {
+        Ok(trades) => HttpResponse::Ok().json(
+            trades.into_iter().map(|trade| UserTrade::new(trade, &user_id)).collect::<Vec<_>>()
+        ),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
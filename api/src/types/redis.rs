@@ -16,10 +16,42 @@ pub enum MessageFromOrderbook {
     OrderCancelled {
         payload: OrderCancelledPayload,
     },
+    #[serde(rename = "ORDER_AMENDED")]
+    OrderAmended {
+        payload: OrderAmendedPayload,
+    },
+    #[serde(rename = "ORDERS_CANCELLED")]
+    OrdersCancelled {
+        payload: Vec<String>,
+    },
+    #[serde(rename = "DEAD_MAN_SWITCH")]
+    DeadManSwitch {
+        payload: DeadManSwitchPayload,
+    },
+    #[serde(rename = "ORDER_REJECTED")]
+    OrderRejected {
+        payload: OrderRejectedPayload,
+    },
     #[serde(rename = "OPEN_ORDERS")]
     OpenOrders {
         payload: Vec<OpenOrder>,
     },
+    #[serde(rename = "ORDER")]
+    Order {
+        payload: OpenOrder,
+    },
+    #[serde(rename = "ASSET_LISTED")]
+    AssetListed {
+        payload: Asset,
+    },
+    #[serde(rename = "MARKET_LISTED")]
+    MarketListed {
+        payload: Box<MarketConfig>,
+    },
+    #[serde(rename = "MARKET_STATUS")]
+    MarketStatusChanged {
+        payload: Market,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,33 +64,67 @@ pub struct DepthPayload {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Fill {
     pub price: String,
-    pub qty: f64,
+    pub qty: String,
     pub trade_id: i64,
+    /// Fee paid on what the order received from this fill
+    #[serde(default)]
+    pub fee: String,
+    #[serde(default)]
+    pub fee_asset: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeadManSwitchPayload {
+    pub timeout_ms: u64,
+    pub cancel_at: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OrderPlacedPayload {
     pub order_id: String,
-    pub executed_qty: f64,
+    pub status: OrderStatus,
+    pub executed_qty: String,
+    pub cancelled_qty: String,
     pub fills: Vec<Fill>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OrderCancelledPayload {
     pub order_id: String,
-    pub executed_qty: f64,
-    pub remaining_qty: f64,
+    pub executed_qty: String,
+    pub remaining_qty: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OrderAmendedPayload {
+    pub order_id: String,
+    pub price: String,
+    pub quantity: String,
+    pub status: OrderStatus,
+    pub executed_qty: String,
+    pub fills: Vec<Fill>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OrderRejectedPayload {
+    /// Machine-readable code such as `POST_ONLY_WOULD_CROSS` or `INSUFFICIENT_FUNDS`
+    pub reason: String,
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OpenOrder {
     pub order_id: String,
-    pub executed_qty: f64,
+    /// The engine calls this `filled`
+    #[serde(alias = "filled")]
+    pub executed_qty: String,
     pub price: String,
     pub quantity: String,
     #[serde(rename = "side")]
     pub side: OrderSide,
     pub user_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +134,53 @@ pub enum OrderSide {
     Sell,
 }
 
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,
+    StopMarket,
+    StopLimit,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    #[default]
+    Gtc,
+    Ioc,
+    Fok,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostOnly {
+    Reject,
+    Reprice,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTradePrevention {
+    #[default]
+    CancelNewest,
+    CancelOldest,
+    CancelBoth,
+    DecrementAndCancel,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Expired,
+    Rejected,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum MessageToEngine {
@@ -79,6 +192,18 @@ pub enum MessageToEngine {
     CancelOrder {
         data: CancelOrderData,
     },
+    #[serde(rename = "AMEND_ORDER")]
+    AmendOrder {
+        data: AmendOrderData,
+    },
+    #[serde(rename = "CANCEL_ALL_ORDERS")]
+    CancelAllOrders {
+        data: CancelAllOrdersData,
+    },
+    #[serde(rename = "SET_DEAD_MAN_SWITCH")]
+    SetDeadManSwitch {
+        data: DeadManSwitchData,
+    },
     #[serde(rename = "ON_RAMP")]
     OnRamp {
         data: OnRampData,
@@ -91,26 +216,101 @@ pub enum MessageToEngine {
     GetOpenOrders {
         data: GetOpenOrdersData,
     },
+    #[serde(rename = "GET_ORDER")]
+    GetOrder {
+        data: GetOrderData,
+    },
+    #[serde(rename = "LIST_ASSET")]
+    ListAsset {
+        data: Asset,
+    },
+    #[serde(rename = "LIST_MARKET")]
+    ListMarket {
+        data: Box<MarketConfig>,
+    },
+    #[serde(rename = "SET_MARKET_STATUS")]
+    SetMarketStatus {
+        data: SetMarketStatusData,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateOrderData {
     pub market: String,
-    pub price: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<String>,
     pub quantity: String,
     #[serde(rename = "side")]
-    pub side: OrderSide
+    pub side: OrderSide,
+    #[serde(default)]
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_only: Option<PostOnly>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_price: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_quantity: Option<String>,
+    #[serde(default)]
+    pub self_trade_prevention: SelfTradePrevention,
+    /// Good-till-date expiry in milliseconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    /// Retrying with the same id returns the original order instead of
+    /// placing another one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
 }
 
+/// Names an order by the engine's order id or by its client order id.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CancelOrderData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    pub market: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetOrderData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    pub market: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CancelAllOrdersData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub market: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<OrderSide>,
+}
+
+/// Cancel all orders unless refreshed within `timeout_ms`; `0` disarms
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeadManSwitchData {
+    pub timeout_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AmendOrderData {
+    #[serde(default)]
     pub order_id: String,
     pub market: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OnRampData {
     pub amount: String,
+    pub asset: String,
     pub user_id: String,
     pub txn_id: String,
 }
@@ -125,3 +325,111 @@ pub struct GetOpenOrdersData {
     pub market: String,
 }
 
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketStatus {
+    #[default]
+    Trading,
+    Auction,
+    CancelOnly,
+    Halted,
+    Delisted,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Market {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub status: MarketStatus,
+}
+
+/// An asset balances are held in; amounts of it may have at most
+/// `precision` decimal places.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Asset {
+    pub symbol: String,
+    pub precision: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetMarketStatusData {
+    #[serde(default)]
+    pub market: String,
+    pub status: MarketStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MarketConfig {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    #[serde(default)]
+    pub status: MarketStatus,
+    #[serde(default)]
+    pub spec: MarketSpec,
+}
+
+/// Trading rules of a market; the engine fills in anything left out.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct MarketSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tick_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_quantity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_quantity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_notional: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_precision: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_band_percent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_price: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<CircuitBreaker>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auction_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matching: Option<MatchingMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allocation: Option<Allocation>,
+    /// Negative for a maker rebate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maker_fee_rate: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taker_fee_rate: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MatchingMode {
+    Continuous,
+    BatchAuction { interval_ms: u64 },
+}
+
+/// How fills are shared among the orders resting at one price.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Allocation {
+    PriceTime,
+    ProRata {
+        #[serde(default)]
+        top_priority: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_allocation: Option<String>,
+    },
+}
+
+/// Halts the market for `halt_secs` after a move of more than
+/// `move_percent` within `window_secs`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CircuitBreaker {
+    pub move_percent: String,
+    pub window_secs: u64,
+    pub halt_secs: u64,
+}
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE order_status_history;
+DROP TABLE order_statuses;
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+
+CREATE TABLE order_statuses (
+    order_id VARCHAR PRIMARY KEY,
+    client_order_id VARCHAR,
+    user_id VARCHAR NOT NULL,
+    market VARCHAR NOT NULL,
+    side VARCHAR NOT NULL,
+    price VARCHAR NOT NULL,
+    quantity VARCHAR NOT NULL,
+    executed_qty VARCHAR NOT NULL,
+    status VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE order_status_history (
+    id SERIAL PRIMARY KEY,
+    order_id VARCHAR NOT NULL,
+    status VARCHAR NOT NULL,
+    executed_qty VARCHAR NOT NULL,
+    changed_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX order_status_history_order_id ON order_status_history (order_id);
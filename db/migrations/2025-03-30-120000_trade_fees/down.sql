@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX trades_seller_id;
+DROP INDEX trades_buyer_id;
+
+ALTER TABLE trades
+    DROP COLUMN seller_fee_asset,
+    DROP COLUMN seller_fee,
+    DROP COLUMN buyer_fee_asset,
+    DROP COLUMN buyer_fee,
+    DROP COLUMN seller_id,
+    DROP COLUMN buyer_id,
+    DROP COLUMN trade_id;
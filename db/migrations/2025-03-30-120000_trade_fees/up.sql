@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+ALTER TABLE trades
+    ADD COLUMN trade_id BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN buyer_id VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN seller_id VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN buyer_fee VARCHAR NOT NULL DEFAULT '0',
+    ADD COLUMN buyer_fee_asset VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN seller_fee VARCHAR NOT NULL DEFAULT '0',
+    ADD COLUMN seller_fee_asset VARCHAR NOT NULL DEFAULT '';
+
+CREATE INDEX trades_buyer_id ON trades (buyer_id);
+CREATE INDEX trades_seller_id ON trades (seller_id);
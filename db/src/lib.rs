@@ -1,6 +1,6 @@
 use diesel::pg::PgConnection;
 use diesel::r2d2::{self, ConnectionManager};
-use schema::{trades, orders};
+use schema::{trades, orders, order_statuses, order_status_history};
 use std::env;
 use redis::Client;
 use serde::{Deserialize, Serialize};
@@ -8,7 +8,7 @@ use tokio;
 use serde_json;
 use validator::Validate;
 use diesel::prelude::*;
-use crate::models::{Trade, Order};
+use crate::models::{Trade, Order, OrderStatusRecord, NewOrderStatusChange};
 use chrono::{TimeZone, Utc};
 use log::info;
 
@@ -21,6 +21,10 @@ pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 pub enum DbMessage {
     TradeAdded(TradeMessage),
     OrderUpdate(OrderMessage),
+    /// A good-till-date order reached its expiry with quantity still open
+    OrderExpired(OrderMessage),
+    /// An order moved to a new status or its fills changed
+    OrderStatusChanged(OrderStatusMessage),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
@@ -33,19 +37,40 @@ pub struct TradeMessage {
     pub quote_quantity: String,
     pub timestamp: i64,
     pub market: String,
+    pub buyer_id: String,
+    pub seller_id: String,
+    pub buyer_fee: String,
+    pub buyer_fee_asset: String,
+    pub seller_fee: String,
+    pub seller_fee_asset: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct OrderMessage {
     #[validate(length(min = 1))]
     pub order_id: String,
-    pub executed_qty: f64,
+    pub executed_qty: String,
     pub market: Option<String>,
     pub price: Option<String>,
     pub quantity: Option<String>,
     pub side: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct OrderStatusMessage {
+    #[validate(length(min = 1))]
+    pub order_id: String,
+    pub client_order_id: Option<String>,
+    pub user_id: String,
+    pub market: String,
+    pub side: String,
+    pub price: String,
+    pub quantity: String,
+    pub executed_qty: String,
+    pub status: String,
+    pub timestamp: i64,
+}
+
 pub fn establish_connection_pool() -> DbPool {
     match dotenvy::dotenv() {
         Ok(_) => println!("Loaded .env file"),
@@ -113,16 +138,32 @@ fn process_message(message: DbMessage, pool: &DbPool) -> Result<(), diesel::resu
                 return Ok(());
             }
 
+            // Engine trade ids are per-market counters, so the row gets its own id
+            let trade_id = match trade_message.id.parse() {
+                Ok(trade_id) => trade_id,
+                Err(e) => {
+                    println!("Invalid trade ID {}: {}", trade_message.id, e);
+                    return Ok(());
+                }
+            };
+
             let trade = Trade {
-                id: uuid::Uuid::parse_str(&trade_message.id).expect("Failed to parse into uuid"),
+                id: uuid::Uuid::new_v4(),
                 is_buyer_maker: trade_message.is_buyer_maker,
                 price: trade_message.price,
                 quantity: trade_message.quantity,
                 quote_quantity: trade_message.quote_quantity,
-                timestamp: Utc.timestamp_opt(trade_message.timestamp, 0)
+                timestamp: Utc.timestamp_millis_opt(trade_message.timestamp)
                     .unwrap()
                     .naive_utc(),
                 market: trade_message.market,
+                trade_id,
+                buyer_id: trade_message.buyer_id,
+                seller_id: trade_message.seller_id,
+                buyer_fee: trade_message.buyer_fee,
+                buyer_fee_asset: trade_message.buyer_fee_asset,
+                seller_fee: trade_message.seller_fee,
+                seller_fee_asset: trade_message.seller_fee_asset,
             };
 
             diesel::insert_into(trades::table)
@@ -130,16 +171,24 @@ fn process_message(message: DbMessage, pool: &DbPool) -> Result<(), diesel::resu
                 .execute(conn)?;
         }
         
-        DbMessage::OrderUpdate(order_message) => {
+        DbMessage::OrderUpdate(order_message) | DbMessage::OrderExpired(order_message) => {
             println!("Processing order update: {}", order_message.order_id);
             if let Err(e) = order_message.validate() {
                 println!("Order validation failed for ID {}: {:?}", order_message.order_id, e);
                 return Ok(());
             }
+
+            let executed_qty = match order_message.executed_qty.parse() {
+                Ok(qty) => qty,
+                Err(e) => {
+                    println!("Invalid executed quantity for ID {}: {}", order_message.order_id, e);
+                    return Ok(());
+                }
+            };
             
             let order = Order {
                 id: uuid::Uuid::parse_str(&order_message.order_id).expect("Failed to parse into uuid"),
-                executed_qty: order_message.executed_qty.to_string().parse().unwrap(),
+                executed_qty,
                 market: order_message.market.unwrap_or_default(),
                 price: order_message.price.unwrap_or_default(),
                 quantity: order_message.quantity.unwrap_or_default(),
@@ -151,6 +200,61 @@ fn process_message(message: DbMessage, pool: &DbPool) -> Result<(), diesel::resu
                 .values(&order)
                 .execute(conn)?;
         }
+
+        DbMessage::OrderStatusChanged(status_message) => {
+            println!("Processing order status: {} {}", status_message.order_id, status_message.status);
+            if let Err(e) = status_message.validate() {
+                println!("Order status validation failed for ID {}: {:?}", status_message.order_id, e);
+                return Ok(());
+            }
+
+            let changed_at = match Utc.timestamp_millis_opt(status_message.timestamp).single() {
+                Some(time) => time.naive_utc(),
+                None => {
+                    println!("Invalid timestamp for ID {}: {}", status_message.order_id, status_message.timestamp);
+                    return Ok(());
+                }
+            };
+
+            let record = OrderStatusRecord {
+                order_id: status_message.order_id.clone(),
+                client_order_id: status_message.client_order_id,
+                user_id: status_message.user_id,
+                market: status_message.market,
+                side: status_message.side,
+                price: status_message.price,
+                quantity: status_message.quantity,
+                executed_qty: status_message.executed_qty.clone(),
+                status: status_message.status.clone(),
+                created_at: changed_at,
+                updated_at: changed_at,
+            };
+
+            // The first status creates the row; later ones keep its creation time
+            conn.transaction(|conn| {
+                diesel::insert_into(order_statuses::table)
+                    .values(&record)
+                    .on_conflict(order_statuses::order_id)
+                    .do_update()
+                    .set((
+                        order_statuses::price.eq(&record.price),
+                        order_statuses::quantity.eq(&record.quantity),
+                        order_statuses::executed_qty.eq(&record.executed_qty),
+                        order_statuses::status.eq(&record.status),
+                        order_statuses::updated_at.eq(record.updated_at),
+                    ))
+                    .execute(conn)?;
+
+                diesel::insert_into(order_status_history::table)
+                    .values(&NewOrderStatusChange {
+                        order_id: status_message.order_id,
+                        status: status_message.status,
+                        executed_qty: status_message.executed_qty,
+                        changed_at,
+                    })
+                    .execute(conn)
+            })?;
+        }
     }
     
     Ok(())
@@ -13,6 +13,7 @@ pub struct User {
     pub password_hash: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub is_admin: bool,
 } 
 #[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::trades)]
@@ -23,7 +24,15 @@ pub struct Trade {
     pub quantity: String,
     pub quote_quantity: String,
     pub timestamp: NaiveDateTime,
-    pub market: String
+    pub market: String,
+    /// Id the engine gave the trade, unique within its market
+    pub trade_id: i64,
+    pub buyer_id: String,
+    pub seller_id: String,
+    pub buyer_fee: String,
+    pub buyer_fee_asset: String,
+    pub seller_fee: String,
+    pub seller_fee_asset: String,
 }
 #[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::orders)]
@@ -35,4 +44,40 @@ pub struct Order {
     pub quantity: String,
     pub side: String,
     pub created_at: NaiveDateTime
-}
\ No newline at end of file
+}
+
+/// Latest known state of an order, one row per engine order id.
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::order_statuses)]
+pub struct OrderStatusRecord {
+    pub order_id: String,
+    pub client_order_id: Option<String>,
+    pub user_id: String,
+    pub market: String,
+    pub side: String,
+    pub price: String,
+    pub quantity: String,
+    pub executed_qty: String,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::order_status_history)]
+pub struct OrderStatusChange {
+    pub id: i32,
+    pub order_id: String,
+    pub status: String,
+    pub executed_qty: String,
+    pub changed_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::order_status_history)]
+pub struct NewOrderStatusChange {
+    pub order_id: String,
+    pub status: String,
+    pub executed_qty: String,
+    pub changed_at: NaiveDateTime,
+}
@@ -23,6 +23,32 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    order_status_history (id) {
+        id -> Int4,
+        order_id -> Varchar,
+        status -> Varchar,
+        executed_qty -> Varchar,
+        changed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_statuses (order_id) {
+        order_id -> Varchar,
+        client_order_id -> Nullable<Varchar>,
+        user_id -> Varchar,
+        market -> Varchar,
+        side -> Varchar,
+        price -> Varchar,
+        quantity -> Varchar,
+        executed_qty -> Varchar,
+        status -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     trades (id) {
         id -> Uuid,
@@ -32,6 +58,13 @@ diesel::table! {
         quote_quantity -> Varchar,
         timestamp -> Timestamp,
         market -> Varchar,
+        trade_id -> Int8,
+        buyer_id -> Varchar,
+        seller_id -> Varchar,
+        buyer_fee -> Varchar,
+        buyer_fee_asset -> Varchar,
+        seller_fee -> Varchar,
+        seller_fee_asset -> Varchar,
     }
 }
 
@@ -43,10 +76,13 @@ diesel::table! {
         password_hash -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        is_admin -> Bool,
     }
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    order_status_history,
+    order_statuses,
     orders,
     trades,
     users,
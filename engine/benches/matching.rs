@@ -0,0 +1,104 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use engine::redis::redis_manager::OrderSide;
+use engine::trade::decimal::Decimal;
+use engine::trade::orderbook::{Order, OrderType, Orderbook, SelfTradePrevention, TimeInForce};
+
+const ORDERS_PER_LEVEL: i64 = 10;
+const BATCH: i64 = 1_000;
+
+fn order(order_id: String, user_id: &str, side: OrderSide, price: i64, quantity: i64) -> Order {
+    Order {
+        price: Decimal::from_int(price),
+        quantity: Decimal::from_int(quantity),
+        order_id,
+        filled: Decimal::ZERO,
+        side,
+        user_id: user_id.to_string(),
+        order_type: OrderType::Limit,
+        time_in_force: TimeInForce::Gtc,
+        post_only: None,
+        stop_price: None,
+        iceberg: None,
+        self_trade_prevention: SelfTradePrevention::CancelNewest,
+        expires_at: None,
+        client_order_id: None,
+    }
+}
+
+/// A book with `levels` ask levels from 10_000 upward and as many bid levels
+/// below, each holding `ORDERS_PER_LEVEL` orders of size 1.
+fn deep_book(levels: i64) -> Orderbook {
+    let mut orderbook = Orderbook::new("BENCH_USDC".to_string());
+    for level in 0..levels {
+        for n in 0..ORDERS_PER_LEVEL {
+            let mut ask = order(format!("ask-{}-{}", level, n), "maker", OrderSide::Sell, 10_000 + level, 1);
+            let mut bid = order(format!("bid-{}-{}", level, n), "maker", OrderSide::Buy, 9_999 - level, 1);
+            orderbook.add_order(&mut ask).unwrap();
+            orderbook.add_order(&mut bid).unwrap();
+        }
+    }
+    orderbook
+}
+
+fn bench_matching(c: &mut Criterion) {
+    let mut group = c.benchmark_group("matching");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    for levels in [100, 1_000] {
+        let book = deep_book(levels);
+
+        // Takers that each fill a single maker at the top of the book
+        group.bench_with_input(BenchmarkId::new("take_top", levels), &book, |b, book| {
+            b.iter_batched(
+                || book.clone(),
+                |mut orderbook| {
+                    for n in 0..BATCH {
+                        let mut taker = order(format!("taker-{}", n), "taker", OrderSide::Buy, 20_000, 1);
+                        black_box(orderbook.add_order(&mut taker).unwrap());
+                    }
+                    // Returned so the book is dropped outside the measurement
+                    orderbook
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        // Limit orders priced away from the touch that only rest
+        group.bench_with_input(BenchmarkId::new("add_resting", levels), &book, |b, book| {
+            b.iter_batched(
+                || book.clone(),
+                |mut orderbook| {
+                    for n in 0..BATCH {
+                        let price = 9_999 - n % levels;
+                        let mut maker = order(format!("resting-{}", n), "maker", OrderSide::Buy, price, 1);
+                        black_box(orderbook.add_order(&mut maker).unwrap());
+                    }
+                    orderbook
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        // Cancels spread over every level of the book
+        group.bench_with_input(BenchmarkId::new("cancel", levels), &book, |b, book| {
+            let ids: Vec<String> = (0..BATCH)
+                .map(|n| format!("bid-{}-{}", n % levels, (n / levels) % ORDERS_PER_LEVEL))
+                .collect();
+            b.iter_batched(
+                || book.clone(),
+                |mut orderbook| {
+                    for id in &ids {
+                        black_box(orderbook.remove_order(id));
+                    }
+                    orderbook
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_matching);
+criterion_main!(benches);
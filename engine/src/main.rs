@@ -1,18 +1,14 @@
 use std::sync::{Arc, Mutex};
 use tokio;
-use crate::redis::redis_manager::RedisManager;
-use crate::trade::engine::Engine;
-use crate::types::api::{MessageFromApi, MessageToApi};
+use engine::redis::redis_manager::RedisManager;
+use engine::trade::engine::{Engine, now_millis};
+use engine::types::api::{MessageFromApi, MessageToApi};
 use serde_json;
 use log::info;
 use env_logger;
 use dotenv::dotenv;
 use serde::Deserialize;
 
-mod types;
-mod redis;
-mod trade;
-
 #[derive(Debug, Deserialize)]
 struct MessageWrapper {
     client_id: String,
@@ -71,6 +67,7 @@ async fn main() {
                 }
             }
         }
+        engine.lock().unwrap().tick(now_millis());
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     }
 }
@@ -5,6 +5,7 @@ use once_cell::sync::Lazy;
 use serde_json;
 use crate::types::ws::WsMessage;
 use crate::types::api::MessageToApi;
+use crate::trade::orderbook::OrderStatus;
 use std::env;
 use dotenv::dotenv;
 use validator::Validate;
@@ -14,6 +15,10 @@ use log::info;
 pub enum DbMessage {
     TradeAdded(TradeMessage),
     OrderUpdate(OrderMessage),
+    /// A good-till-date order reached its expiry with quantity still open
+    OrderExpired(OrderMessage),
+    /// An order moved to a new status or its fills changed
+    OrderStatusChanged(OrderStatusMessage),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
@@ -25,18 +30,38 @@ pub struct TradeMessage {
     pub quote_quantity: String,
     pub timestamp: i64,
     pub market: String,
+    pub buyer_id: String,
+    pub seller_id: String,
+    pub buyer_fee: String,
+    pub buyer_fee_asset: String,
+    pub seller_fee: String,
+    pub seller_fee_asset: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderMessage {
     pub order_id: String,
-    pub executed_qty: f64,
+    pub executed_qty: String,
     pub market: Option<String>,
     pub price: Option<String>,
     pub quantity: Option<String>,
     pub side: Option<OrderSide>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderStatusMessage {
+    pub order_id: String,
+    pub client_order_id: Option<String>,
+    pub user_id: String,
+    pub market: String,
+    pub side: OrderSide,
+    pub price: String,
+    pub quantity: String,
+    pub executed_qty: String,
+    pub status: OrderStatus,
+    pub timestamp: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum OrderSide {
@@ -56,6 +81,12 @@ pub struct RedisManager {
     db_client: Client,
 }
 
+impl Default for RedisManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RedisManager {
     pub fn new() -> Self {
         info!("Initializing new RedisManager");
@@ -113,11 +144,13 @@ impl RedisManager {
         
         // Wrap the message in the expected format
         let wrapped_message = match &message {
-            MessageToApi::OrderPlaced { order_id, executed_qty, fills } => serde_json::json!({
+            MessageToApi::OrderPlaced { order_id, status, executed_qty, cancelled_qty, fills } => serde_json::json!({
                 "type": "ORDER_PLACED",
                 "payload": {
                     "order_id": order_id,
+                    "status": status,
                     "executed_qty": executed_qty,
+                    "cancelled_qty": cancelled_qty,
                     "fills": fills
                 }
             }),
@@ -0,0 +1,81 @@
+use std::fs;
+use serde::{Deserialize, Serialize};
+use crate::trade::decimal::Decimal;
+use crate::types::api::RejectReason;
+
+/// Registry entry for something users hold balances in. Markets trade one
+/// listed asset against another.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Asset {
+    pub symbol: String,
+    /// Most fractional digits an amount of this asset may have
+    pub precision: u32,
+}
+
+impl Asset {
+    /// Assets listed when no assets file is present.
+    pub fn defaults() -> Vec<Asset> {
+        vec![
+            Asset { symbol: "SOL".to_string(), precision: 8 },
+            Asset { symbol: "USDC".to_string(), precision: 6 },
+        ]
+    }
+
+    /// Reads a JSON array of assets.
+    pub fn load(path: &str) -> Result<Vec<Asset>, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let assets: Vec<Asset> = serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+        for asset in &assets {
+            asset.validate().map_err(|reason| format!("{}: {}: {}", path, asset.symbol, reason))?;
+        }
+        Ok(assets)
+    }
+
+    pub fn validate(&self) -> Result<(), RejectReason> {
+        if self.symbol.is_empty() || self.precision > Decimal::DECIMALS {
+            return Err(RejectReason::InvalidAsset);
+        }
+        Ok(())
+    }
+
+    /// Smallest amount of this asset that can be held.
+    pub fn unit(&self) -> Decimal {
+        Decimal::from_int(1) / Decimal::from_int(10i64.pow(self.precision))
+    }
+
+    /// Whether `amount` fits within this asset's precision.
+    pub fn fits(&self, amount: Decimal) -> bool {
+        amount.scale() <= self.precision
+    }
+
+    /// Cuts `amount` down to this asset's precision, rounding toward zero.
+    pub fn truncate(&self, amount: Decimal) -> Decimal {
+        if amount < Decimal::ZERO {
+            -(-amount).floor_to(self.unit())
+        } else {
+            amount.floor_to(self.unit())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_precision_bounds_amounts() {
+        let usdc = Asset { symbol: "USDC".to_string(), precision: 6 };
+        assert_eq!(usdc.unit(), dec("0.000001"));
+        assert!(usdc.fits(dec("12.345678")));
+        assert!(!usdc.fits(dec("12.3456789")));
+        assert_eq!(usdc.truncate(dec("0.12345678")), dec("0.123456"));
+        assert_eq!(usdc.truncate(dec("-0.12345678")), dec("-0.123456"));
+
+        assert_eq!(Asset { symbol: String::new(), precision: 2 }.validate(), Err(RejectReason::InvalidAsset));
+        assert_eq!(Asset { symbol: "X".to_string(), precision: 9 }.validate(), Err(RejectReason::InvalidAsset));
+    }
+}
@@ -0,0 +1,92 @@
+use std::cmp::Reverse;
+use serde::{Deserialize, Serialize};
+use crate::trade::decimal::Decimal;
+use crate::trade::orderbook::Level;
+
+/// Where an auction would uncross if it ended now.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Equilibrium {
+    pub price: Decimal,
+    /// Quantity that trades at `price`
+    pub volume: Decimal,
+    /// Demand minus supply at `price`; what is left unmatched on one side
+    pub imbalance: Decimal,
+}
+
+/// A market collecting orders without matching them until `ends_at` (ms).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Auction {
+    pub ends_at: i64,
+    /// Last equilibrium published to subscribers
+    pub indicative: Option<Equilibrium>,
+}
+
+/// Finds the single price that executes the most volume between `bids`
+/// (best first) and `asks` (best first), given as (price, total quantity)
+/// levels. Ties go to the smallest imbalance, then to the price closest to
+/// `reference` (ignored unless positive), then to the lower price. `None`
+/// if the book does not cross.
+pub fn equilibrium(bids: &[Level], asks: &[Level], reference: Decimal) -> Option<Equilibrium> {
+    let mut candidates: Vec<Decimal> = bids.iter().chain(asks).map(|&(price, _)| price).collect();
+    candidates.sort();
+    candidates.dedup();
+
+    // Ranks candidates by volume, then smallest imbalance, then closeness to
+    // the reference; prices are walked upwards so full ties keep the lower one
+    let rank = |eq: &Equilibrium| {
+        let distance = if reference.is_positive() { (eq.price - reference).abs() } else { Decimal::ZERO };
+        (eq.volume, Reverse(eq.imbalance.abs()), Reverse(distance))
+    };
+    let mut best: Option<Equilibrium> = None;
+    for price in candidates {
+        let demand: Decimal = bids.iter().filter(|&&(p, _)| p >= price).map(|&(_, q)| q).sum();
+        let supply: Decimal = asks.iter().filter(|&&(p, _)| p <= price).map(|&(_, q)| q).sum();
+        let volume = demand.min(supply);
+        if !volume.is_positive() {
+            continue;
+        }
+        let candidate = Equilibrium { price, volume, imbalance: demand - supply };
+        if best.as_ref().is_none_or(|current| rank(&candidate) > rank(current)) {
+            best = Some(candidate);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(levels: &[(i64, i64)]) -> Vec<Level> {
+        levels.iter().map(|&(p, q)| (Decimal::from_int(p), Decimal::from_int(q))).collect()
+    }
+
+    #[test]
+    fn test_equilibrium_maximizes_volume() {
+        let bids = levels(&[(102, 3), (101, 2), (99, 5)]);
+        let asks = levels(&[(98, 2), (100, 2), (101, 4)]);
+
+        // At 101: demand 5, supply 8 -> 5; at 100: demand 5, supply 4 -> 4
+        let eq = equilibrium(&bids, &asks, Decimal::ZERO).unwrap();
+        assert_eq!(eq.price, Decimal::from_int(101));
+        assert_eq!(eq.volume, Decimal::from_int(5));
+        assert_eq!(eq.imbalance, Decimal::from_int(-3));
+    }
+
+    #[test]
+    fn test_equilibrium_tie_breaks() {
+        // 97 and 98 both trade 5 with an imbalance of 1
+        let bids = levels(&[(100, 1), (98, 5)]);
+        let asks = levels(&[(97, 5), (99, 1)]);
+        assert_eq!(equilibrium(&bids, &asks, Decimal::ZERO).unwrap().price, Decimal::from_int(97));
+        assert_eq!(equilibrium(&bids, &asks, Decimal::from_int(120)).unwrap().price, Decimal::from_int(98));
+    }
+
+    #[test]
+    fn test_equilibrium_needs_a_crossed_book() {
+        let bids = levels(&[(99, 5)]);
+        let asks = levels(&[(100, 5)]);
+        assert_eq!(equilibrium(&bids, &asks, Decimal::ZERO), None);
+        assert_eq!(equilibrium(&[], &asks, Decimal::ZERO), None);
+    }
+}
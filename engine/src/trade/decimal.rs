@@ -0,0 +1,282 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// Fixed-point number with `DECIMALS` fractional digits, stored as a scaled integer.
+///
+/// Used for every price, quantity and balance in the engine so that fills and
+/// balance updates are exact. It serializes as a canonical decimal string
+/// (`"100"`, `"0.5"`) so values round-trip unchanged through the API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Decimal(i128);
+
+impl Decimal {
+    pub const DECIMALS: u32 = 8;
+    pub const SCALE: i128 = 100_000_000;
+    pub const ZERO: Decimal = Decimal(0);
+    /// Smallest representable increment.
+    pub const EPSILON: Decimal = Decimal(1);
+
+    pub const fn from_int(value: i64) -> Self {
+        Decimal(value as i128 * Self::SCALE)
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn abs(self) -> Self {
+        Decimal(self.0.abs())
+    }
+
+    /// Number of fractional digits needed to write this value exactly.
+    pub fn scale(&self) -> u32 {
+        let mut raw = self.0;
+        let mut scale = Self::DECIMALS;
+        while scale > 0 && raw % 10 == 0 {
+            raw /= 10;
+            scale -= 1;
+        }
+        scale
+    }
+
+    /// Whether this value is a whole multiple of `step`. A zero step accepts
+    /// everything.
+    pub fn is_multiple_of(&self, step: Decimal) -> bool {
+        step.0 == 0 || self.0 % step.0 == 0
+    }
+
+    /// Rounds down to a whole multiple of `step`. A zero step leaves the
+    /// value as it is.
+    pub fn floor_to(self, step: Decimal) -> Decimal {
+        if step.0 == 0 {
+            return self;
+        }
+        Decimal(self.0 - self.0.rem_euclid(step.0))
+    }
+
+    /// Multiplies two values, truncating any digits beyond `DECIMALS`.
+    pub fn checked_mul(self, other: Decimal) -> Option<Decimal> {
+        self.0.checked_mul(other.0).map(|v| Decimal(v / Self::SCALE))
+    }
+
+    /// Divides two values, truncating any digits beyond `DECIMALS`.
+    pub fn checked_div(self, other: Decimal) -> Option<Decimal> {
+        if other.0 == 0 {
+            return None;
+        }
+        self.0.checked_mul(Self::SCALE).map(|v| Decimal(v / other.0))
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (int_part, frac_part) = match digits.split_once('.') {
+            Some((i, f)) => (i, f),
+            None => (digits, ""),
+        };
+
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(format!("Invalid decimal: {:?}", s));
+        }
+        if !int_part.chars().all(|c| c.is_ascii_digit()) || !frac_part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("Invalid decimal: {:?}", s));
+        }
+
+        let frac_part = frac_part.trim_end_matches('0');
+        if frac_part.len() > Self::DECIMALS as usize {
+            return Err(format!("Too many decimal places in {:?} (max {})", s, Self::DECIMALS));
+        }
+
+        let int_value = if int_part.is_empty() {
+            0
+        } else {
+            int_part.parse::<i128>().map_err(|_| format!("Invalid decimal: {:?}", s))?
+        };
+        let frac_value = if frac_part.is_empty() {
+            0
+        } else {
+            frac_part.parse::<i128>().map_err(|_| format!("Invalid decimal: {:?}", s))?
+                * 10_i128.pow(Self::DECIMALS - frac_part.len() as u32)
+        };
+
+        let raw = int_value
+            .checked_mul(Self::SCALE)
+            .and_then(|v| v.checked_add(frac_value))
+            .ok_or_else(|| format!("Decimal out of range: {:?}", s))?;
+
+        Ok(Decimal(if negative { -raw } else { raw }))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let scale = Self::SCALE as u128;
+        let int_part = abs / scale;
+        let frac_part = abs % scale;
+
+        if frac_part == 0 {
+            write!(f, "{}{}", sign, int_part)
+        } else {
+            let frac = format!("{:0width$}", frac_part, width = Self::DECIMALS as usize);
+            write!(f, "{}{}.{}", sign, int_part, frac.trim_end_matches('0'))
+        }
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Accept plain JSON numbers as well so older snapshots still load
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Str(String),
+            Int(i64),
+            Float(f64),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Str(s) => s.parse().map_err(serde::de::Error::custom),
+            Repr::Int(i) => Ok(Decimal::from_int(i)),
+            Repr::Float(f) => f.to_string().parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Decimal) -> Decimal {
+        Decimal(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Decimal {
+    fn add_assign(&mut self, other: Decimal) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: Decimal) -> Decimal {
+        Decimal(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Decimal {
+    fn sub_assign(&mut self, other: Decimal) {
+        self.0 -= other.0;
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Decimal;
+
+    fn mul(self, other: Decimal) -> Decimal {
+        self.checked_mul(other).expect("decimal multiplication overflow")
+    }
+}
+
+impl Div for Decimal {
+    type Output = Decimal;
+
+    fn div(self, other: Decimal) -> Decimal {
+        self.checked_div(other).expect("decimal division by zero or overflow")
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal(-self.0)
+    }
+}
+
+impl Sum for Decimal {
+    fn sum<I: Iterator<Item = Decimal>>(iter: I) -> Decimal {
+        iter.fold(Decimal::ZERO, |acc, d| acc + d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display_round_trip() {
+        for s in ["0", "1", "100", "0.5", "123.456", "0.00000001", "-42.1"] {
+            assert_eq!(dec(s).to_string(), s);
+        }
+        assert_eq!(dec("100.000").to_string(), "100");
+        assert_eq!(dec(".5").to_string(), "0.5");
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_input() {
+        assert!("".parse::<Decimal>().is_err());
+        assert!("abc".parse::<Decimal>().is_err());
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("1e5".parse::<Decimal>().is_err());
+        assert!("0.000000001".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn test_arithmetic_is_exact() {
+        let mut remaining = dec("1");
+        for _ in 0..10 {
+            remaining -= dec("0.1");
+        }
+        assert_eq!(remaining, Decimal::ZERO);
+        assert_eq!(dec("0.1") + dec("0.2"), dec("0.3"));
+        assert_eq!(dec("2.5") * dec("4"), dec("10"));
+        assert_eq!(dec("0.3") * dec("101.7"), dec("30.51"));
+        assert_eq!(dec("10") / dec("4"), dec("2.5"));
+        assert_eq!(dec("1") / dec("3"), dec("0.33333333"));
+    }
+
+    #[test]
+    fn test_scale_and_multiples() {
+        assert_eq!(dec("100").scale(), 0);
+        assert_eq!(dec("1.25").scale(), 2);
+        assert_eq!(dec("0.00000001").scale(), 8);
+        assert!(dec("1.25").is_multiple_of(dec("0.05")));
+        assert!(!dec("1.26").is_multiple_of(dec("0.05")));
+        assert!(dec("1.26").is_multiple_of(Decimal::ZERO));
+        assert_eq!(dec("1.26").floor_to(dec("0.05")), dec("1.25"));
+        assert_eq!(dec("1.26").floor_to(Decimal::ZERO), dec("1.26"));
+    }
+
+    #[test]
+    fn test_serde_uses_strings() {
+        let json = serde_json::to_string(&dec("12.34")).unwrap();
+        assert_eq!(json, "\"12.34\"");
+        let parsed: Decimal = serde_json::from_str("\"12.34\"").unwrap();
+        assert_eq!(parsed, dec("12.34"));
+        let from_number: Decimal = serde_json::from_str("5").unwrap();
+        assert_eq!(from_number, dec("5"));
+    }
+}
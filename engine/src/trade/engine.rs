@@ -1,38 +1,138 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use serde::{Deserialize, Serialize};
-use crate::trade::orderbook::{Orderbook, Order, Fill};
+use crate::trade::orderbook::{Orderbook, Order, OrderType, OrderStatus, Fill, Iceberg, Execution, TimeInForce};
+use crate::trade::decimal::Decimal;
+use crate::trade::asset::Asset;
+use crate::trade::market::{Market, MarketConfig, MarketStatus, MatchingMode, PriceWindow};
+use crate::trade::auction::{self, Auction};
+use crate::trade::trigger::TriggerBook;
 use std::fs;
 use serde_json;
-use crate::types::api::{MessageFromApi, MessageToApi, DepthPayload};
+use crate::types::api::{MessageFromApi, MessageToApi, DepthPayload, CreateOrderData, AmendOrderData, OrderAmendedPayload, DeadManSwitchPayload, RejectReason};
 use crate::redis::redis_manager::RedisManager;
-use crate::redis::redis_manager::{DbMessage, OrderMessage, TradeMessage, OrderSide};
+use crate::redis::redis_manager::{DbMessage, OrderMessage, OrderStatusMessage, TradeMessage, OrderSide};
 use rand::{thread_rng, Rng, distributions::Alphanumeric};
-use crate::types::ws::{WsMessage, WsMessageData, TradeData, DepthData};
+use crate::types::ws::{WsMessage, WsMessageData, TradeData, DepthData, StatusData, AuctionData, OrderUpdateData};
 use log::info;
-pub const BASE_CURRENCY: &str = "INR";
+/// Where the engine reads its assets from unless `ASSETS_CONFIG_PATH` says otherwise
+pub const DEFAULT_ASSETS_CONFIG_PATH: &str = "./assets.json";
+/// Where the engine reads its markets from unless `MARKETS_CONFIG_PATH` says otherwise
+pub const DEFAULT_MARKETS_CONFIG_PATH: &str = "./markets.json";
+pub const MAX_CLIENT_ORDER_ID_LEN: usize = 64;
+/// Account that trading fees are credited to and maker rebates paid from
+pub const FEE_ACCOUNT: &str = "exchange_fees";
 
 
+/// Current time in milliseconds since the epoch.
+pub fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserBalance {
-    available: f64,
-    locked: f64,
+    available: Decimal,
+    locked: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlacedOrder {
+    pub order_id: String,
+    pub status: OrderStatus,
+    pub executed_qty: Decimal,
+    pub cancelled_qty: Decimal,
+    pub fills: Vec<Fill>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Engine {
     pub orderbooks: Vec<Orderbook>,
+    assets: HashMap<String, Asset>,
+    markets: HashMap<String, Market>,
     balances: HashMap<String, HashMap<String, UserBalance>>,
+    trigger_books: HashMap<String, TriggerBook>,
+    #[serde(default)]
+    price_windows: HashMap<String, PriceWindow>,
+    /// Markets halted by their circuit breaker, with when to resume them (ms)
+    timed_halts: HashMap<String, i64>,
+    auctions: HashMap<String, Auction>,
+    /// When each batch auction market clears next (ms)
+    #[serde(default)]
+    next_batches: HashMap<String, i64>,
+    /// Good-till-date orders as (expires_at, order_id, market), soonest first.
+    /// Entries of orders that filled or were cancelled are skipped when due.
+    expiries: BTreeSet<(i64, String, String)>,
+    /// Users with an armed dead man's switch, with when to cancel all of
+    /// their orders (ms)
+    dead_man_switches: HashMap<String, i64>,
+    /// User -> market -> client order id -> engine order id, for open orders
+    /// placed with a client order id. Sending the same id again while the
+    /// order is open gets the order as it now stands.
+    client_orders: HashMap<String, HashMap<String, HashMap<String, String>>>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
-        info!("Initializing engine with SOL_USDC orderbook");
+        let path = std::env::var("ASSETS_CONFIG_PATH").unwrap_or_else(|_| DEFAULT_ASSETS_CONFIG_PATH.to_string());
+        let assets = if fs::metadata(&path).is_ok() {
+            info!("Loading assets from {}", path);
+            Asset::load(&path).unwrap_or_else(|e| panic!("Invalid assets config {}", e))
+        } else {
+            info!("No assets config at {}, listing default assets", path);
+            Asset::defaults()
+        };
+
+        let path = std::env::var("MARKETS_CONFIG_PATH").unwrap_or_else(|_| DEFAULT_MARKETS_CONFIG_PATH.to_string());
+        let configs = if fs::metadata(&path).is_ok() {
+            info!("Loading markets from {}", path);
+            MarketConfig::load(&path).unwrap_or_else(|e| panic!("Invalid markets config {}", e))
+        } else {
+            info!("No markets config at {}, listing default markets", path);
+            MarketConfig::defaults()
+        };
+        Self::with_config(assets, configs)
+    }
+
+    /// Engine trading `configs` over the default assets.
+    pub fn with_markets(configs: Vec<MarketConfig>) -> Self {
+        Self::with_config(Asset::defaults(), configs)
+    }
+
+    pub fn with_config(assets: Vec<Asset>, configs: Vec<MarketConfig>) -> Self {
         let mut engine = Self {
-            orderbooks: vec![
-                Orderbook::new("SOL_USDC".to_string()),
-            ],
+            orderbooks: Vec::new(),
+            assets: HashMap::new(),
+            markets: HashMap::new(),
             balances: HashMap::new(),
+            trigger_books: HashMap::new(),
+            price_windows: HashMap::new(),
+            timed_halts: HashMap::new(),
+            auctions: HashMap::new(),
+            next_batches: HashMap::new(),
+            expiries: BTreeSet::new(),
+            dead_man_switches: HashMap::new(),
+            client_orders: HashMap::new(),
         };
+        for asset in assets {
+            let symbol = asset.symbol.clone();
+            if let Err(reason) = engine.list_asset(asset) {
+                panic!("Cannot list asset {}: {}", symbol, reason);
+            }
+        }
+        for config in configs {
+            let symbol = config.market.symbol.clone();
+            if let Err(reason) = engine.list_market(config, now_millis()) {
+                panic!("Cannot list market {}: {}", symbol, reason);
+            }
+        }
 
         engine.set_base_balances();
         info!("Engine initialized with orderbooks: {:?}", engine.orderbooks.iter().map(|ob| ob.ticker()).collect::<Vec<_>>());
@@ -41,12 +141,32 @@ impl Engine {
 
     #[allow(dead_code)]
     pub fn save_snapshot(&self) {
-        let snapshot = serde_json::json!({
+        fs::write("./snapshot.json", serde_json::to_string_pretty(&self.snapshot()).unwrap()).unwrap();
+    }
+
+    /// Orderbooks, asset and market registries (including each market's
+    /// status), balances, pending stop orders, good-till-date expiries,
+    /// armed dead man's switches and client order ids of open orders.
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
             "orderbooks": self.orderbooks.iter().map(|o| o.get_snapshot()).collect::<Vec<_>>(),
+            "assets": self.assets.clone(),
+            "markets": self.markets.clone(),
             "balances": self.balances.clone(),
-        });
-        
-        fs::write("./snapshot.json", serde_json::to_string_pretty(&snapshot).unwrap()).unwrap();
+            "trigger_books": self.trigger_books.clone(),
+            "timed_halts": self.timed_halts.clone(),
+            "auctions": self.auctions.clone(),
+            "expiries": self.expiries.clone(),
+            "dead_man_switches": self.dead_man_switches.clone(),
+            "client_orders": self.client_orders.clone(),
+        })
+    }
+
+    /// Rebuilds an engine from a `snapshot`. Price windows and batch
+    /// schedules start afresh.
+    #[allow(dead_code)]
+    pub fn restore(snapshot: serde_json::Value) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(snapshot)
     }
 
     fn set_base_balances(&mut self) {
@@ -58,92 +178,250 @@ impl Engine {
     fn ensure_user_balance(&mut self, user_id: &str) {
         if !self.balances.contains_key(user_id) {
             info!("Creating new balance for user: {}", user_id);
-            let mut user_balance = HashMap::new();
-            
-            // Add all required currencies
-            for currency in ["SOL", "USDC", "INR"].iter() {
-                user_balance.insert(currency.to_string(), UserBalance {
-                    available: 10_000_000.0,
-                    locked: 0.0,
-                });
+        }
+        let user_balance = self.balances.entry(user_id.to_string()).or_default();
+
+        // Add every listed asset, including those listed since the last order
+        for currency in self.assets.keys() {
+            user_balance.entry(currency.to_string()).or_insert_with(|| UserBalance {
+                available: Decimal::from_int(10_000_000),
+                locked: Decimal::ZERO,
+            });
+        }
+    }
+
+    /// Base and quote asset of a listed market.
+    fn market_assets(&self, market: &str) -> Result<(String, String), RejectReason> {
+        self.markets.get(market)
+            .map(|m| (m.base_asset.clone(), m.quote_asset.clone()))
+            .ok_or(RejectReason::UnknownMarket)
+    }
+
+    /// Fails with `UnknownMarket` for unlisted markets and with `reason` if
+    /// the market's status does not `allow` the request.
+    fn check_market_status(&self, market: &str, allow: fn(MarketStatus) -> bool, reason: RejectReason) -> Result<(), RejectReason> {
+        let listed = self.markets.get(market).ok_or(RejectReason::UnknownMarket)?;
+        if allow(listed.status) {
+            Ok(())
+        } else {
+            Err(reason)
+        }
+    }
+
+    /// Whether the market currently rests orders without matching them,
+    /// either in an opening auction or as a batch auction market.
+    fn collects_orders(&self, market: &str) -> bool {
+        let batch = self.orderbooks.iter()
+            .find(|o| o.ticker() == market)
+            .is_some_and(|o| o.spec().matching != MatchingMode::Continuous);
+        batch || self.auctions.contains_key(market)
+    }
+
+    /// Moves a market to `status`. A market that resumes from a halt goes
+    /// through an opening auction first if it has one configured, and asking
+    /// a market in auction to trade ends the auction at the next tick. Any
+    /// other move out of an auction abandons it, and whatever it left crossed
+    /// on the book is uncrossed once the market trades again.
+    pub fn set_market_status(&mut self, market: &str, status: MarketStatus, now: i64) -> Result<Market, RejectReason> {
+        let auction_secs = self.orderbooks.iter()
+            .find(|o| o.ticker() == market)
+            .and_then(|o| o.spec().auction_secs);
+        let listed = self.markets.get_mut(market).ok_or(RejectReason::UnknownMarket)?;
+
+        if let (MarketStatus::Auction, MarketStatus::Trading) = (listed.status, status) {
+            if let Some(auction) = self.auctions.get_mut(market) {
+                auction.ends_at = auction.ends_at.min(now);
+                return Ok(listed.clone());
+            }
+        }
+        let status = match (listed.status, status) {
+            (MarketStatus::Halted, MarketStatus::Trading) if auction_secs.is_some() => MarketStatus::Auction,
+            _ => status,
+        };
+
+        info!("Market {} status {:?} -> {:?}", market, listed.status, status);
+        listed.status = status;
+        let listed = listed.clone();
+        // An explicit status change overrides any circuit-breaker halt
+        self.timed_halts.remove(market);
+        match status {
+            MarketStatus::Auction => {
+                let ends_at = now + auction_secs.unwrap_or(0) as i64 * 1000;
+                self.auctions.insert(market.to_string(), Auction { ends_at, indicative: None });
+            }
+            // Orders keep collecting while the market is halted mid-auction
+            MarketStatus::Halted => {}
+            _ => {
+                self.auctions.remove(market);
             }
-            
-            self.balances.insert(user_id.to_string(), user_balance);
-            info!("Created balances for currencies: SOL, USDC, INR");
         }
+        // Batch auction books keep clearing on their own schedule
+        if status == MarketStatus::Trading && !self.collects_orders(market) {
+            self.uncross_market(market);
+        }
+        Ok(listed)
     }
 
-    // Update get_base_asset to handle market pairs correctly
-    fn get_base_asset(market: &str) -> &str {
-        market  // Return full market name instead of splitting
+    /// Adds an asset to the registry. Listing it again is a no-op as long as
+    /// its precision stays the same.
+    pub fn list_asset(&mut self, asset: Asset) -> Result<Asset, RejectReason> {
+        asset.validate()?;
+        if self.assets.get(&asset.symbol).is_some_and(|listed| listed.precision != asset.precision) {
+            return Err(RejectReason::InvalidAsset);
+        }
+        info!("Listed asset {}: {:?}", asset.symbol, asset);
+        self.assets.insert(asset.symbol.clone(), asset.clone());
+        Ok(asset)
+    }
+
+    /// Lists a new market, or updates the trading rules and status of one
+    /// that is already listed. A listed market keeps its assets, since
+    /// resting orders hold funds in them.
+    pub fn list_market(&mut self, config: MarketConfig, now: i64) -> Result<MarketConfig, RejectReason> {
+        config.validate()?;
+        let MarketConfig { market, spec } = config.clone();
+        // Quantities are moved in the base asset and prices in the quote asset
+        let (Some(base), Some(quote)) = (self.assets.get(&market.base_asset), self.assets.get(&market.quote_asset)) else {
+            return Err(RejectReason::UnknownAsset);
+        };
+        if !base.fits(spec.step_size) || !quote.fits(spec.tick_size) {
+            return Err(RejectReason::AssetPrecisionExceeded);
+        }
+        let opening_auction = spec.auction_secs.is_some();
+
+        let listed = match self.markets.get(&market.symbol) {
+            Some(listed) => {
+                if listed.base_asset != market.base_asset || listed.quote_asset != market.quote_asset {
+                    return Err(RejectReason::MarketAssetsChanged);
+                }
+                if let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market.symbol) {
+                    orderbook.set_spec(spec);
+                }
+                info!("Updated market {}: {:?}", market.symbol, config);
+                self.set_market_status(&market.symbol, market.status, now)?
+            }
+            None => {
+                self.add_orderbook(Orderbook::with_spec(market.symbol.clone(), spec));
+                info!("Listed market {}: {:?}", market.symbol, config);
+                self.markets.insert(market.symbol.clone(), market.clone());
+                // A newly listed market opens with an auction if it has one
+                if market.status == MarketStatus::Trading && opening_auction {
+                    self.set_market_status(&market.symbol, MarketStatus::Auction, now)?
+                } else {
+                    market
+                }
+            }
+        };
+        Ok(MarketConfig { market: listed, ..config })
     }
 
     pub fn process(&mut self, message: MessageFromApi, client_id: String, user_id: String) {
         match message {
             MessageFromApi::CreateOrder { data } => {
-                match self.create_order(&data.market, &data.price, &data.quantity, data.side, &user_id) {
-                    Ok((executed_qty, fills, order_id)) => {
+                match self.create_order(&data, &user_id) {
+                    Ok(placed) => {
                         RedisManager::get_instance().lock().unwrap().send_to_api(
                             &client_id,
                             MessageToApi::OrderPlaced {
-                                order_id,
-                                executed_qty,
-                                fills,
+                                order_id: placed.order_id,
+                                status: placed.status,
+                                executed_qty: placed.executed_qty,
+                                cancelled_qty: placed.cancelled_qty,
+                                fills: placed.fills,
                             }
                         ).unwrap();
                     }
-                    Err(e) => {
-                        info!("Order error: {}", e);
+                    Err(reason) => {
+                        info!("Order rejected: {}", reason);
                         RedisManager::get_instance().lock().unwrap().send_to_api(
                             &client_id,
-                            MessageToApi::Error {
-                                message: e,
-                            }
+                            reason.into(),
                         ).unwrap();
                     }
                 }
             }
 
-            MessageFromApi::CancelOrder { order_id, market, .. } => {
-                if let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) {
-                    match orderbook.get_open_orders(&order_id).first() {
-                        Some(order) => {
-                            let quote_asset = market.split('_').nth(1).unwrap_or(BASE_CURRENCY);
-                            if order.side == OrderSide::Buy {
-                                if let Ok(price) = orderbook.cancel_bid(&order_id) {
-                                    let left_quantity = (order.quantity - order.filled) * order.price;
-                                    if let Some(balance) = self.balances.get_mut(&order.user_id) {
-                                        if let Some(asset_balance) = balance.get_mut(BASE_CURRENCY) {
-                                            asset_balance.available += left_quantity;
-                                            asset_balance.locked -= left_quantity;
-                                        }
-                                    }
-                                    self.send_updated_depth_at(price, &market);
-                                }
-                            } else {
-                                if let Ok(price) = orderbook.cancel_ask(&order_id) {
-                                    let left_quantity = order.quantity - order.filled;
-                                    if let Some(balance) = self.balances.get_mut(&order.user_id) {
-                                        if let Some(asset_balance) = balance.get_mut(quote_asset) {
-                                            asset_balance.available += left_quantity;
-                                            asset_balance.locked -= left_quantity;
-                                        }
-                                    }
-                                    self.send_updated_depth_at(price, &market);
-                                }
-                            }
-                        }
-                        None => println!("No order found"),
+            MessageFromApi::AmendOrder { data } => {
+                let message = match self.amend_order(&data, &user_id) {
+                    Ok(payload) => MessageToApi::OrderAmended { payload },
+                    Err(reason) => {
+                        info!("Amend rejected: {}", reason);
+                        reason.into()
                     }
-                } else {
-                    println!("No orderbook found");
+                };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send amend response to API: {:?}", e);
+                }
+            }
+
+            MessageFromApi::CancelOrder { data } => {
+                let cancelled = self.resolve_order_id(&user_id, &data.market, data.order_id.as_deref(), data.client_order_id.as_deref())
+                    .and_then(|order_id| self.cancel_order(&order_id, &data.market, &user_id));
+                let message = match cancelled {
+                    Ok(order) => MessageToApi::OrderCancelled {
+                        order_id: order.order_id,
+                        executed_qty: order.filled,
+                        remaining_qty: order.quantity - order.filled,
+                    },
+                    Err(reason) => {
+                        info!("Cancel rejected: {}", reason);
+                        reason.into()
+                    }
+                };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send cancel confirmation to API: {:?}", e);
+                }
+            }
+
+            MessageFromApi::CancelAllOrders { data } => {
+                let message = match self.cancel_all_orders(&user_id, data.market.as_deref(), data.side.as_ref()) {
+                    Ok(order_ids) => MessageToApi::OrdersCancelled { payload: order_ids },
+                    Err(reason) => {
+                        info!("Mass cancel rejected: {}", reason);
+                        reason.into()
+                    }
+                };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send mass cancel confirmation to API: {:?}", e);
+                }
+            }
+
+            MessageFromApi::SetDeadManSwitch { data } => {
+                let cancel_at = self.set_dead_man_switch(&user_id, data.timeout_ms, now_millis());
+                let message = MessageToApi::DeadManSwitch {
+                    payload: DeadManSwitchPayload { timeout_ms: data.timeout_ms, cancel_at },
+                };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send dead man's switch confirmation to API: {:?}", e);
+                }
+            }
+
+            MessageFromApi::FireDeadManSwitch => {
+                let message = MessageToApi::OrdersCancelled { payload: self.fire_dead_man_switch(&user_id) };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send mass cancel confirmation to API: {:?}", e);
+                }
+            }
+
+            MessageFromApi::GetOrder { data } => {
+                let order = self.resolve_order_id(&user_id, &data.market, data.order_id.as_deref(), data.client_order_id.as_deref())
+                    .and_then(|order_id| self.get_order(&order_id, &data.market, &user_id));
+                let message = match order {
+                    Ok(order) => MessageToApi::Order { payload: order },
+                    Err(reason) => reason.into(),
+                };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send order to API: {:?}", e);
                 }
             }
 
             MessageFromApi::GetOpenOrders { data } => {
                 info!("Getting open orders for market: {:?}", data.market);
                 if let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == data.market) {
-                    let orders = orderbook.get_open_orders(&data.user_id);
+                    let mut orders = orderbook.get_open_orders(&data.user_id);
+                    if let Some(trigger_book) = self.trigger_books.get(&data.market) {
+                        orders.extend(trigger_book.get_open_orders(&data.user_id));
+                    }
                     info!("Found orders: {:?}", orders);
                     {
                         info!("About to attempt Redis lock");
@@ -185,9 +463,11 @@ impl Engine {
                 }
             }
 
-            MessageFromApi::OnRamp { amount, user_id, .. } => {
-                let amount = amount.parse::<f64>().unwrap_or(0.0);
-                self.on_ramp(&user_id, amount);
+            MessageFromApi::OnRamp { data } => {
+                let amount = data.amount.parse::<Decimal>().unwrap_or(Decimal::ZERO);
+                if let Err(reason) = self.on_ramp(&data.user_id, &data.asset, amount) {
+                    info!("On-ramp {} rejected: {}", data.txn_id, reason);
+                }
             }
 
             MessageFromApi::GetDepth { data } => {
@@ -242,31 +522,105 @@ impl Engine {
                     }
                 }
             }
+
+            MessageFromApi::ListAsset { data } => {
+                let message = match self.list_asset(data) {
+                    Ok(payload) => MessageToApi::AssetListed { payload },
+                    Err(reason) => {
+                        info!("Asset listing rejected: {}", reason);
+                        reason.into()
+                    }
+                };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send asset listing response to API: {:?}", e);
+                }
+            }
+
+            MessageFromApi::ListMarket { data } => {
+                let message = match self.list_market(*data, now_millis()) {
+                    Ok(payload) => {
+                        self.publish_market_status(&payload.market);
+                        MessageToApi::MarketListed { payload: Box::new(payload) }
+                    }
+                    Err(reason) => {
+                        info!("Market listing rejected: {}", reason);
+                        reason.into()
+                    }
+                };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send market listing response to API: {:?}", e);
+                }
+            }
+
+            MessageFromApi::SetMarketStatus { data } => {
+                let message = match self.set_market_status(&data.market, data.status, now_millis()) {
+                    Ok(payload) => {
+                        self.publish_market_status(&payload);
+                        MessageToApi::MarketStatusChanged { payload }
+                    }
+                    Err(reason) => reason.into(),
+                };
+                if let Err(e) = RedisManager::get_instance().lock().unwrap().send_to_api(&client_id, message) {
+                    info!("Failed to send market status response to API: {:?}", e);
+                }
+            }
         }
     }
 
-    #[allow(dead_code)]
-    pub fn add_orderbook(&mut self, orderbook: Orderbook) {
+    fn add_orderbook(&mut self, orderbook: Orderbook) {
         self.orderbooks.push(orderbook);
     }
 
-    pub fn create_order(
-        &mut self,
-        market: &str,
-        price: &str,
-        quantity: &str,
-        side: OrderSide,
-        user_id: &str,
-    ) -> Result<(f64, Vec<Fill>, String), String> {
+    /// Places an order, or returns the order as it now stands if the user
+    /// already has an open order in the market with this client order id.
+    pub fn create_order(&mut self, data: &CreateOrderData, user_id: &str) -> Result<PlacedOrder, RejectReason> {
+        let Some(client_order_id) = &data.client_order_id else {
+            return self.place_order(data, user_id);
+        };
+        if client_order_id.is_empty() || client_order_id.len() > MAX_CLIENT_ORDER_ID_LEN {
+            return Err(RejectReason::InvalidClientOrderId);
+        }
+        let market = data.market.as_str();
+        if let Ok(order) = self.resolve_order_id(user_id, market, None, Some(client_order_id))
+            .and_then(|order_id| self.get_order(&order_id, market, user_id))
+        {
+            info!("Replaying client order {} of {}", client_order_id, user_id);
+            return Ok(PlacedOrder {
+                order_id: order.order_id.clone(),
+                status: order.fill_status(),
+                executed_qty: order.filled,
+                cancelled_qty: Decimal::ZERO,
+                fills: Vec::new(),
+            });
+        }
+
+        // Only orders left open keep their client order id; a rejected order
+        // was never placed and a finished one frees it for reuse
+        let placed = self.place_order(data, user_id)?;
+        if self.get_order(&placed.order_id, market, user_id).is_ok() {
+            self.client_orders
+                .entry(user_id.to_string())
+                .or_default()
+                .entry(market.to_string())
+                .or_default()
+                .insert(client_order_id.clone(), placed.order_id.clone());
+        }
+        Ok(placed)
+    }
+
+    fn place_order(&mut self, data: &CreateOrderData, user_id: &str) -> Result<PlacedOrder, RejectReason> {
+        let market = data.market.as_str();
         info!("Creating order for market: {:?}", market);
         
         self.ensure_user_balance(user_id);
+        let (base_asset, quote_asset) = self.market_assets(market)?;
+        self.check_market_status(market, MarketStatus::accepts_orders, RejectReason::MarketNotTrading)?;
         
         // Find orderbook by full market name
         let orderbook = self.orderbooks
-            .iter_mut()
+            .iter()
             .find(|o| o.ticker() == market)  // Compare with full market name
-            .ok_or_else(|| format!("No orderbook found for {}", market))?;
+            .ok_or(RejectReason::UnknownMarket)?;
 
         // Generate order ID
         let order_id: String = thread_rng()
@@ -275,72 +629,801 @@ impl Engine {
             .map(char::from)
             .collect();
 
-        // Create and process order
-        let mut order = Order {
-            price: price.parse::<f64>().map_err(|_| "Invalid price")?,
-            quantity: quantity.parse::<f64>().map_err(|_| "Invalid quantity")?,
-            order_id: order_id.clone(),
-            filled: 0.0,
-            side: side.clone(),
-            user_id: user_id.to_string(),
+        // Market orders take whatever price the book offers
+        let price = match data.order_type {
+            OrderType::Limit | OrderType::StopLimit => data.price.as_deref()
+                .ok_or(RejectReason::MissingPrice)?
+                .parse::<Decimal>().map_err(|_| RejectReason::InvalidPrice)?,
+            OrderType::Market | OrderType::StopMarket => Decimal::ZERO,
+        };
+
+        let stop_price = match data.order_type {
+            OrderType::StopMarket | OrderType::StopLimit => Some(data.stop_price.as_deref()
+                .ok_or(RejectReason::MissingStopPrice)?
+                .parse::<Decimal>().map_err(|_| RejectReason::InvalidStopPrice)?),
+            OrderType::Limit | OrderType::Market => None,
+        };
+
+        // Only orders with a limit price can rest and hide part of their size
+        let iceberg = match (data.display_quantity.as_deref(), data.order_type) {
+            (None, _) => None,
+            (Some(display_quantity), OrderType::Limit | OrderType::StopLimit) => {
+                let display_quantity = display_quantity.parse::<Decimal>()
+                    .map_err(|_| RejectReason::InvalidDisplayQuantity)?;
+                if !display_quantity.is_positive() {
+                    return Err(RejectReason::InvalidDisplayQuantity);
+                }
+                Some(Iceberg { display_quantity, displayed: display_quantity })
+            }
+            (Some(_), OrderType::Market | OrderType::StopMarket) => return Err(RejectReason::InvalidDisplayQuantity),
+        };
+
+        // Create and process order
+        let order = Order {
+            price,
+            quantity: data.quantity.parse::<Decimal>().map_err(|_| RejectReason::InvalidQuantity)?,
+            order_id: order_id.clone(),
+            filled: Decimal::ZERO,
+            side: data.side.clone(),
+            user_id: user_id.to_string(),
+            order_type: data.order_type,
+            time_in_force: data.time_in_force,
+            post_only: data.post_only,
+            stop_price,
+            iceberg,
+            self_trade_prevention: data.self_trade_prevention,
+            expires_at: data.expires_at,
+            client_order_id: data.client_order_id.clone(),
+        };
+        // Only orders that can wait, on the book or for their trigger, can expire
+        if let Some(expires_at) = order.expires_at {
+            if expires_at <= now_millis() || (order.stop_price.is_none() && !order.can_rest()) {
+                return Err(RejectReason::InvalidExpiry);
+            }
+        }
+        orderbook.spec().validate(&order)?;
+        orderbook.spec().check_price_band(&order, orderbook.last_price())?;
+
+        // Stop orders wait in the trigger book with their funds locked up front
+        if order.stop_price.is_some() {
+            let reserved = Self::stop_reservation(&order);
+            let asset = match order.side {
+                OrderSide::Buy => &quote_asset,
+                OrderSide::Sell => &base_asset,
+            };
+            self.lock_funds(user_id, asset, reserved)?;
+            self.update_db_orders(&order, Decimal::ZERO, &Vec::new(), market);
+            self.record_order_status(&order, OrderStatus::New, market);
+            self.schedule_expiry(order.expires_at, &order_id, market);
+            self.trigger_books.entry(market.to_string()).or_default().add(order);
+
+            return Ok(PlacedOrder {
+                order_id,
+                status: OrderStatus::New,
+                executed_qty: Decimal::ZERO,
+                cancelled_qty: Decimal::ZERO,
+                fills: Vec::new(),
+            });
+        }
+
+        if self.collects_orders(market) && !order.can_rest() {
+            return Err(RejectReason::NotAllowedInAuction);
+        }
+
+        // Funds are locked before the order can match. Market orders have no
+        // limit price to lock against, so buys lock what sweeping the current
+        // book would cost and sells lock the full quantity
+        let reserved = match (data.order_type, &data.side) {
+            (OrderType::Market, OrderSide::Buy) => {
+                let (_, cost) = orderbook.estimate_fill(&order);
+                self.lock_funds(user_id, &quote_asset, cost)?;
+                cost
+            }
+            (OrderType::Market, OrderSide::Sell) => {
+                self.lock_funds(user_id, &base_asset, order.quantity)?;
+                order.quantity
+            }
+            _ => self.check_and_lock_funds(&order, &base_asset, &quote_asset)?,
+        };
+
+        let expires_at = order.expires_at;
+        let placed = self.execute_order(order, market, reserved)?;
+        if matches!(placed.status, OrderStatus::New | OrderStatus::PartiallyFilled) {
+            self.schedule_expiry(expires_at, &placed.order_id, market);
+        }
+        self.process_triggers(market);
+        Ok(placed)
+    }
+
+    /// Engine order id of one of `user_id`'s orders in `market`, given
+    /// either that id or the client order id the order was placed with.
+    pub fn resolve_order_id(&self, user_id: &str, market: &str, order_id: Option<&str>, client_order_id: Option<&str>) -> Result<String, RejectReason> {
+        match (order_id, client_order_id) {
+            (Some(order_id), _) => Ok(order_id.to_string()),
+            (None, Some(client_order_id)) => self.client_orders
+                .get(user_id)
+                .and_then(|markets| markets.get(market))
+                .and_then(|orders| orders.get(client_order_id))
+                .cloned()
+                .ok_or(RejectReason::OrderNotFound),
+            (None, None) => Err(RejectReason::OrderNotFound),
+        }
+    }
+
+    /// One of `user_id`'s open orders in `market`, resting on the book or
+    /// waiting to trigger.
+    pub fn get_order(&self, order_id: &str, market: &str, user_id: &str) -> Result<Order, RejectReason> {
+        let orderbook = self.orderbooks
+            .iter()
+            .find(|o| o.ticker() == market)
+            .ok_or(RejectReason::UnknownMarket)?;
+        orderbook.get_order(order_id)
+            .or_else(|| self.trigger_books.get(market).and_then(|t| t.get_order(order_id)))
+            .filter(|o| o.user_id == user_id)
+            .cloned()
+            .ok_or(RejectReason::OrderNotFound)
+    }
+
+    /// Cancels one of `user_id`'s open orders, whether it rests on the book or
+    /// is a stop order still waiting to trigger, and releases its funds.
+    pub fn cancel_order(&mut self, order_id: &str, market: &str, user_id: &str) -> Result<Order, RejectReason> {
+        self.check_market_status(market, MarketStatus::accepts_cancels, RejectReason::MarketHalted)?;
+        let order = self.remove_open_order(order_id, market, user_id)?;
+        self.record_order_status(&order, OrderStatus::Cancelled, market);
+        Ok(order)
+    }
+
+    fn remove_open_order(&mut self, order_id: &str, market: &str, user_id: &str) -> Result<Order, RejectReason> {
+        // Stop orders that have not triggered yet live in the trigger book
+        if let Some(order) = self.trigger_books
+            .get_mut(market)
+            .and_then(|trigger_book| trigger_book.cancel(order_id, user_id))
+        {
+            let (base_asset, quote_asset) = self.market_assets(market)?;
+            let asset = match order.side {
+                OrderSide::Buy => quote_asset,
+                OrderSide::Sell => base_asset,
+            };
+            self.unlock_funds(user_id, &asset, Self::stop_reservation(&order));
+            return Ok(order);
+        }
+
+        let orderbook = self.orderbooks
+            .iter_mut()
+            .find(|o| o.ticker() == market)
+            .ok_or(RejectReason::UnknownMarket)?;
+        if orderbook.get_order(order_id).filter(|o| o.user_id == user_id).is_none() {
+            return Err(RejectReason::OrderNotFound);
+        }
+        let order = orderbook.remove_order(order_id).ok_or(RejectReason::OrderNotFound)?;
+
+        self.unlock_order_funds(&order, order.quantity - order.filled, market);
+        self.send_updated_depth_at(order.price, &order.side, market);
+        Ok(order)
+    }
+
+    /// Cancels every open order of `user_id`, including stop orders waiting
+    /// to trigger, optionally only in `market` and/or on `side`. Each market
+    /// gets a single depth update for all the levels that changed. Without a
+    /// market filter, halted markets are skipped.
+    pub fn cancel_all_orders(&mut self, user_id: &str, market: Option<&str>, side: Option<&OrderSide>) -> Result<Vec<String>, RejectReason> {
+        let mut markets: Vec<String> = match market {
+            Some(market) => {
+                self.market_assets(market)?;
+                self.check_market_status(market, MarketStatus::accepts_cancels, RejectReason::MarketHalted)?;
+                vec![market.to_string()]
+            }
+            None => self.markets.values()
+                .filter(|m| m.status.accepts_cancels())
+                .map(|m| m.symbol.clone())
+                .collect(),
+        };
+        markets.sort();
+        Ok(self.cancel_orders_in(user_id, markets, side))
+    }
+
+    fn cancel_orders_in(&mut self, user_id: &str, markets: Vec<String>, side: Option<&OrderSide>) -> Vec<String> {
+        let on_side = |order: &Order| side.is_none_or(|side| order.side == *side);
+
+        let mut cancelled = Vec::new();
+        for market in markets {
+            let stops: Vec<Order> = self.trigger_books.get(&market)
+                .map(|trigger_book| trigger_book.get_open_orders(user_id))
+                .unwrap_or_default();
+            for order in stops.into_iter().filter(on_side) {
+                if let Ok(order) = self.remove_open_order(&order.order_id, &market, user_id) {
+                    self.record_order_status(&order, OrderStatus::Cancelled, &market);
+                    cancelled.push(order.order_id);
+                }
+            }
+
+            let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) else {
+                continue;
+            };
+            let removed: Vec<Order> = orderbook.get_open_orders(user_id)
+                .into_iter()
+                .filter(on_side)
+                .filter_map(|order| orderbook.remove_order(&order.order_id))
+                .collect();
+            if removed.is_empty() {
+                continue;
+            }
+
+            let (mut bid_prices, mut ask_prices) = (Vec::new(), Vec::new());
+            for order in removed {
+                self.unlock_order_funds(&order, order.quantity - order.filled, &market);
+                self.record_order_status(&order, OrderStatus::Cancelled, &market);
+                match order.side {
+                    OrderSide::Buy => bid_prices.push(order.price),
+                    OrderSide::Sell => ask_prices.push(order.price),
+                }
+                cancelled.push(order.order_id);
+            }
+            self.send_updated_depth_levels(&bid_prices, &ask_prices, &market);
+        }
+        cancelled
+    }
+
+    /// Arms or refreshes `user_id`'s dead man's switch so that all of their
+    /// orders are cancelled `timeout_ms` from `now` unless it is set again
+    /// first. A zero timeout disarms it. Returns when the switch fires.
+    pub fn set_dead_man_switch(&mut self, user_id: &str, timeout_ms: u64, now: i64) -> Option<i64> {
+        if timeout_ms == 0 {
+            self.dead_man_switches.remove(user_id);
+            return None;
+        }
+        let cancel_at = now + timeout_ms as i64;
+        self.dead_man_switches.insert(user_id.to_string(), cancel_at);
+        Some(cancel_at)
+    }
+
+    /// Disarms `user_id`'s dead man's switch and cancels all of their orders.
+    /// Unlike a mass cancel this reaches into halted markets too, so that a
+    /// client that went away has nothing left on the book when trading
+    /// resumes.
+    pub fn fire_dead_man_switch(&mut self, user_id: &str) -> Vec<String> {
+        self.dead_man_switches.remove(user_id);
+        let mut markets: Vec<String> = self.markets.keys().cloned().collect();
+        markets.sort();
+        self.cancel_orders_in(user_id, markets, None)
+    }
+
+    /// Changes the price and/or total quantity of one of `user_id`'s resting
+    /// orders. A smaller quantity at the same price keeps the order's place in
+    /// the queue; any other change re-enters it at the back of its (new) price
+    /// level, where it may trade straight away. Locked funds follow the new
+    /// size of the order.
+    pub fn amend_order(&mut self, data: &AmendOrderData, user_id: &str) -> Result<OrderAmendedPayload, RejectReason> {
+        let market = data.market.as_str();
+        let (base_asset, quote_asset) = self.market_assets(market)?;
+        self.check_market_status(market, MarketStatus::accepts_orders, RejectReason::MarketNotTrading)?;
+
+        let orderbook = self.orderbooks
+            .iter()
+            .find(|o| o.ticker() == market)
+            .ok_or(RejectReason::UnknownMarket)?;
+        let current = orderbook.get_order(&data.order_id)
+            .filter(|o| o.user_id == user_id)
+            .cloned()
+            .ok_or(RejectReason::OrderNotFound)?;
+
+        let mut order = current.clone();
+        if let Some(price) = data.price.as_deref() {
+            order.price = price.parse::<Decimal>().map_err(|_| RejectReason::InvalidPrice)?;
+        }
+        if let Some(quantity) = data.quantity.as_deref() {
+            order.quantity = quantity.parse::<Decimal>().map_err(|_| RejectReason::InvalidQuantity)?;
+        }
+        orderbook.spec().validate(&order)?;
+        if order.price != current.price {
+            orderbook.spec().check_price_band(&order, orderbook.last_price())?;
+        }
+        if order.quantity <= order.filled {
+            return Err(RejectReason::InvalidQuantity);
+        }
+
+        let keeps_priority = order.price == current.price && order.quantity <= current.quantity;
+        if !keeps_priority {
+            if let Some(mode) = order.post_only {
+                orderbook.apply_post_only(&mut order, mode)?;
+            }
+        }
+
+        let (asset, held, needed) = match order.side {
+            OrderSide::Buy => (
+                quote_asset,
+                (current.quantity - current.filled) * current.price,
+                (order.quantity - order.filled) * order.price,
+            ),
+            OrderSide::Sell => (
+                base_asset,
+                current.quantity - current.filled,
+                order.quantity - order.filled,
+            ),
+        };
+
+        if keeps_priority {
+            if let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) {
+                orderbook.reduce_order(&order.order_id, order.quantity);
+            }
+            self.unlock_funds(user_id, &asset, held - needed);
+            self.update_db_orders(&order, order.filled, &Vec::new(), market);
+            self.record_order_status(&order, order.fill_status(), market);
+            self.send_updated_depth_at(order.price, &order.side, market);
+
+            return Ok(OrderAmendedPayload {
+                status: order.fill_status(),
+                order_id: order.order_id,
+                price: order.price,
+                quantity: order.quantity,
+                executed_qty: order.filled,
+                fills: Vec::new(),
+            });
+        }
+
+        // Everything that can fail happens before the order leaves the book,
+        // so a failed amend leaves it as it was
+        if self.collects_orders(market) && !order.can_rest() {
+            return Err(RejectReason::NotAllowedInAuction);
+        }
+        if needed > held {
+            self.lock_funds(user_id, &asset, needed - held)?;
+        }
+        if let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) {
+            orderbook.remove_order(&order.order_id);
+        }
+
+        let (price, quantity, side) = (order.price, order.quantity, order.side.clone());
+        let placed = self.execute_order(order, market, held.max(needed))?;
+        if price != current.price {
+            self.send_updated_depth_at(current.price, &side, market);
+        }
+        self.process_triggers(market);
+
+        Ok(OrderAmendedPayload {
+            order_id: placed.order_id,
+            price,
+            quantity,
+            status: placed.status,
+            executed_qty: current.filled + placed.executed_qty,
+            fills: placed.fills,
+        })
+    }
+
+    /// Runs an order through the book and settles the result. `reserved` is
+    /// what was locked for it up front (quote for buys, base for sells); any
+    /// part of it that is neither spent nor backing a resting remainder is
+    /// released.
+    fn execute_order(&mut self, mut order: Order, market: &str, reserved: Decimal) -> Result<PlacedOrder, RejectReason> {
+        let (base_asset, quote_asset) = self.market_assets(market)?;
+        let user_id = order.user_id.clone();
+        let side = order.side.clone();
+        let reserved_asset = match side {
+            OrderSide::Buy => quote_asset.clone(),
+            OrderSide::Sell => base_asset.clone(),
+        };
+        let requested_qty = order.quantity;
+
+        let orderbook = self.orderbooks
+            .iter_mut()
+            .find(|o| o.ticker() == market)
+            .ok_or(RejectReason::UnknownMarket)?;
+
+        // A market buy can only spend what was locked for it
+        if order.order_type == OrderType::Market && side == OrderSide::Buy {
+            order.quantity = order.quantity.min(orderbook.affordable_quantity(&order, reserved));
+        }
+
+        // With price bands, a market order becomes an IOC limit at the band
+        // edge so it cannot sweep the book beyond it
+        if order.order_type == OrderType::Market {
+            if let Some((low, high)) = orderbook.spec().price_band(orderbook.last_price()) {
+                order.order_type = OrderType::Limit;
+                order.time_in_force = TimeInForce::Ioc;
+                order.price = match side {
+                    OrderSide::Buy => high,
+                    OrderSide::Sell => low,
+                };
+            }
+        }
+
+        // During an auction orders only rest; they trade when it uncrosses
+        let in_auction = self.auctions.contains_key(market);
+        let result = if in_auction && !order.can_rest() {
+            Err(RejectReason::NotAllowedInAuction)
+        } else if in_auction {
+            orderbook.rest_order(&mut order);
+            Ok(Execution::default())
+        } else {
+            orderbook.add_order(&mut order)
+        };
+        let Execution { mut fills, executed_qty, cancelled_makers, makers, .. } = match result {
+            Ok(execution) => execution,
+            Err(reason) => {
+                self.unlock_funds(&user_id, &reserved_asset, reserved);
+                self.record_order_status(&order, OrderStatus::Rejected, market);
+                return Err(reason);
+            }
+        };
+
+        self.charge_fees(&mut fills, &side, market, false);
+        self.update_balance(&user_id, &base_asset, &quote_asset, &side, &fills, executed_qty);
+
+        let used = match side {
+            OrderSide::Buy => fills.iter().map(|f| f.qty * f.price).sum(),
+            OrderSide::Sell => executed_qty,
+        };
+        let remaining = order.quantity - order.filled;
+        let backing_rest = match (order.can_rest(), &side) {
+            (false, _) => Decimal::ZERO,
+            (true, OrderSide::Buy) => remaining * order.price,
+            (true, OrderSide::Sell) => remaining,
+        };
+        self.unlock_funds(&user_id, &reserved_asset, reserved - used - backing_rest);
+
+        // Resting orders of the same user removed by self-trade prevention
+        for (maker, cancelled) in &cancelled_makers {
+            self.unlock_order_funds(maker, *cancelled, market);
+            self.update_db_orders(maker, maker.filled, &Vec::new(), market);
+            self.send_updated_depth_at(maker.price, &maker.side, market);
+        }
+        for maker in &makers {
+            self.record_order_status(maker, maker.fill_status(), market);
+        }
+        self.record_cancelled(&cancelled_makers, market);
+
+        info!("Creating db trades");
+        self.create_db_trades(&fills, market, &user_id, &side);
+        info!("Updating db orders");
+        self.update_db_orders(&order, executed_qty, &fills, market);
+        info!("Publishing ws depth updates");
+        self.publish_ws_depth_updates(&fills, order.price, &side, market);
+        info!("Publishing ws trades");
+        self.publish_ws_trades(&fills, &user_id, market);
+        self.check_circuit_breaker(market, &fills);
+
+        // Whatever did not fill and is not resting was cancelled, whether by
+        // time in force or by self-trade prevention
+        let resting_qty = if order.can_rest() {
+            order.quantity - order.filled
+        } else {
+            Decimal::ZERO
+        };
+        let cancelled_qty = requested_qty - order.filled - resting_qty;
+        let status = if order.filled == requested_qty {
+            OrderStatus::Filled
+        } else if cancelled_qty.is_positive() {
+            OrderStatus::Cancelled
+        } else if order.filled.is_positive() {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::New
+        };
+        self.record_order_status(&order, status, market);
+
+        Ok(PlacedOrder {
+            order_id: order.order_id,
+            status,
+            executed_qty,
+            cancelled_qty,
+            fills,
+        })
+    }
+
+    fn schedule_expiry(&mut self, expires_at: Option<i64>, order_id: &str, market: &str) {
+        if let Some(expires_at) = expires_at {
+            self.expiries.insert((expires_at, order_id.to_string(), market.to_string()));
+        }
+    }
+
+    /// Cancels a good-till-date order that is still open, whatever the
+    /// market's status, and tells its owner and the db processor.
+    fn expire_order(&mut self, order_id: &str, market: &str) {
+        let user_id = self.orderbooks.iter()
+            .find(|o| o.ticker() == market)
+            .and_then(|o| o.get_order(order_id))
+            .or_else(|| self.trigger_books.get(market).and_then(|t| t.get_order(order_id)))
+            .map(|o| o.user_id.clone());
+        let Some(user_id) = user_id else {
+            return;
+        };
+        let Ok(order) = self.remove_open_order(order_id, market, &user_id) else {
+            return;
+        };
+
+        info!("Order {} on {} expired", order_id, market);
+        let message = DbMessage::OrderExpired(OrderMessage {
+            order_id: order.order_id.clone(),
+            executed_qty: order.filled.to_string(),
+            market: Some(market.to_string()),
+            price: Some(order.price.to_string()),
+            quantity: Some(order.quantity.to_string()),
+            side: Some(order.side.clone()),
+        });
+        if let Err(e) = RedisManager::get_instance().lock().unwrap().push_message_to_db_processor(message) {
+            info!("Failed to push order expiry: {}", e);
+        }
+        self.record_order_status(&order, OrderStatus::Expired, market);
+        self.publish_order_update(&order, OrderStatus::Expired, market);
+    }
+
+    /// Funds locked when a stop order is placed: the full limit value for
+    /// stop-limit buys, the value at the stop price for stop-market buys and
+    /// the quantity for sells.
+    fn stop_reservation(order: &Order) -> Decimal {
+        match (&order.side, order.order_type) {
+            (OrderSide::Buy, OrderType::StopLimit) => order.quantity * order.price,
+            (OrderSide::Buy, _) => order.quantity * order.stop_price.unwrap_or_default(),
+            (OrderSide::Sell, _) => order.quantity,
+        }
+    }
+
+    /// Activates stop orders whose stop price the last trade has reached.
+    /// Activated orders can trade and trigger further stops, so keep going
+    /// until the trigger book is quiet.
+    fn process_triggers(&mut self, market: &str) {
+        loop {
+            // A circuit breaker may have halted the market along the way
+            if self.check_market_status(market, MarketStatus::accepts_orders, RejectReason::MarketNotTrading).is_err() {
+                return;
+            }
+            // Stops wait for an auction to uncross; ending it runs them.
+            // Batch auction books reject triggered orders that cannot rest
+            if self.auctions.contains_key(market) {
+                return;
+            }
+            let last_price = match self.orderbooks.iter().find(|o| o.ticker() == market) {
+                Some(orderbook) => orderbook.last_price(),
+                None => return,
+            };
+            // Nothing has traded yet, so there is no price to trigger on
+            if !last_price.is_positive() {
+                return;
+            }
+
+            let triggered = match self.trigger_books.get_mut(market) {
+                Some(trigger_book) => trigger_book.take_triggered(last_price),
+                None => return,
+            };
+            if triggered.is_empty() {
+                return;
+            }
+
+            for mut order in triggered {
+                info!("Stop order {} triggered at {}", order.order_id, last_price);
+                let reserved = Self::stop_reservation(&order);
+                order.order_type = match order.order_type {
+                    OrderType::StopMarket => OrderType::Market,
+                    _ => OrderType::Limit,
+                };
+                if let Err(reason) = self.execute_order(order, market, reserved) {
+                    info!("Triggered order rejected: {}", reason);
+                }
+            }
+        }
+    }
+
+    /// Feeds new trades to the market's circuit breaker and halts the market
+    /// for the configured time if it trips.
+    fn check_circuit_breaker(&mut self, market: &str, fills: &[Fill]) {
+        let Some(breaker) = self.orderbooks.iter()
+            .find(|o| o.ticker() == market)
+            .and_then(|o| o.spec().circuit_breaker.clone())
+        else {
+            return;
+        };
+        let now = now_millis();
+        let window = self.price_windows.entry(market.to_string()).or_default();
+        if !fills.iter().any(|fill| window.record(&breaker, now, fill.price)) {
+            return;
+        }
+
+        window.clear();
+        info!("Circuit breaker tripped on {}, halting for {}s", market, breaker.halt_secs);
+        if let Ok(halted) = self.set_market_status(market, MarketStatus::Halted, now) {
+            self.timed_halts.insert(market.to_string(), now + breaker.halt_secs as i64 * 1000);
+            self.publish_market_status(&halted);
+        }
+    }
+
+    /// Runs time-driven work; called from the main loop. Fires dead man's
+    /// switches, expires good-till-date orders, resumes markets whose
+    /// circuit-breaker halt is over, publishes indicative auction prices,
+    /// uncrosses auctions that have ended and clears due batch auctions.
+    pub fn tick(&mut self, now: i64) {
+        let expired: Vec<String> = self.dead_man_switches.iter()
+            .filter(|(_, &cancel_at)| cancel_at <= now)
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        for user_id in expired {
+            let cancelled = self.fire_dead_man_switch(&user_id);
+            info!("Dead man's switch of {} fired, cancelled {} orders", user_id, cancelled.len());
+        }
+
+        while let Some((expires_at, _, _)) = self.expiries.first() {
+            if *expires_at > now {
+                break;
+            }
+            let (_, order_id, market) = self.expiries.pop_first().unwrap();
+            self.expire_order(&order_id, &market);
+        }
+
+        let resumed: Vec<String> = self.timed_halts.iter()
+            .filter(|(_, &resume_at)| resume_at <= now)
+            .map(|(market, _)| market.clone())
+            .collect();
+        for market in resumed {
+            self.timed_halts.remove(&market);
+            // An admin may have changed the status during the halt
+            if self.markets.get(&market).map(|m| m.status) != Some(MarketStatus::Halted) {
+                continue;
+            }
+            if let Ok(resumed) = self.set_market_status(&market, MarketStatus::Trading, now) {
+                info!("Circuit breaker halt on {} is over, resuming trading", market);
+                self.publish_market_status(&resumed);
+            }
+        }
+
+        let auctions: Vec<String> = self.auctions.keys().cloned().collect();
+        for market in auctions {
+            // Orders keep collecting while the market is halted mid-auction
+            if self.markets.get(&market).map(|m| m.status) != Some(MarketStatus::Auction) {
+                continue;
+            }
+            let Some(equilibrium) = self.orderbooks.iter()
+                .find(|o| o.ticker() == market)
+                .map(|o| {
+                    let (bids, asks) = o.level_totals();
+                    auction::equilibrium(&bids, &asks, o.last_price())
+                })
+            else {
+                continue;
+            };
+
+            let Some(auction) = self.auctions.get_mut(&market) else {
+                continue;
+            };
+            if auction.ends_at <= now {
+                self.end_auction(&market, now);
+            } else if auction.indicative != equilibrium {
+                auction.indicative = equilibrium;
+                let ends_at = auction.ends_at;
+                self.publish_auction(&market, equilibrium, ends_at);
+            }
+        }
+
+        let batches: Vec<(String, u64)> = self.orderbooks.iter()
+            .filter_map(|o| match o.spec().matching {
+                MatchingMode::BatchAuction { interval_ms } => Some((o.ticker().to_string(), interval_ms)),
+                MatchingMode::Continuous => None,
+            })
+            .collect();
+        for (market, interval_ms) in batches {
+            if self.markets.get(&market).map(|m| m.status) == Some(MarketStatus::Trading) {
+                self.clear_batch(&market, now, interval_ms);
+            }
+        }
+    }
+
+    /// Uncrosses an opening auction and returns the market to continuous
+    /// trading.
+    fn end_auction(&mut self, market: &str, now: i64) {
+        self.auctions.remove(market);
+        self.uncross_market(market);
+        if let Ok(trading) = self.set_market_status(market, MarketStatus::Trading, now) {
+            self.publish_market_status(&trading);
+        }
+        self.process_triggers(market);
+    }
+
+    /// Clears a batch auction market and schedules its next batch.
+    fn clear_batch(&mut self, market: &str, now: i64, interval_ms: u64) {
+        let due = *self.next_batches.entry(market.to_string()).or_insert(now + interval_ms as i64);
+        if due > now {
+            return;
+        }
+        self.next_batches.insert(market.to_string(), now + interval_ms as i64);
+        self.uncross_market(market);
+        self.process_triggers(market);
+    }
+
+    /// Executes everything that crosses on the book at its equilibrium
+    /// price and settles the trades.
+    fn uncross_market(&mut self, market: &str) {
+        let Ok((base_asset, quote_asset)) = self.market_assets(market) else {
+            return;
+        };
+        let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) else {
+            return;
+        };
+        let (bids_before, asks_before) = orderbook.level_totals();
+        let Some(equilibrium) = auction::equilibrium(&bids_before, &asks_before, orderbook.last_price()) else {
+            return;
         };
 
-        let (fills, executed_qty) = orderbook.add_order(&mut order)?;
+        info!("Uncrossing {} at {} for {}", market, equilibrium.price, equilibrium.volume);
+        let mut uncross = orderbook.uncross(equilibrium.price);
+        let (bids_after, asks_after) = orderbook.level_totals();
 
-        self.update_balance(user_id, market, market, &side, &fills, executed_qty)?;
-        info!("Creating db trades");
-        self.create_db_trades(&fills, market, user_id);
-        info!("Updating db orders");
-        self.update_db_orders(&order, executed_qty, &fills, market);
-        info!("Publishing ws depth updates");
-        self.publish_ws_depth_updates(&fills, price.to_string(), &side, market);
-        info!("Publishing ws trades");
-        self.publish_ws_trades(&fills, user_id, market);
+        for (buy, fills) in &mut uncross.trades {
+            let executed_qty = fills.iter().map(|f| f.qty).sum();
+            self.charge_fees(fills, &OrderSide::Buy, market, true);
+            self.update_balance(&buy.user_id, &base_asset, &quote_asset, &OrderSide::Buy, fills, executed_qty);
+            // Buys were locked at their limit price but pay the auction price
+            let improvement = fills.iter().map(|f| f.qty * (buy.price - f.price)).sum();
+            self.unlock_funds(&buy.user_id, &quote_asset, improvement);
+            self.create_db_trades(fills, market, &buy.user_id, &OrderSide::Buy);
+            self.update_db_orders(buy, buy.filled, fills, market);
+            self.publish_ws_trades(fills, &buy.user_id, market);
+            self.record_order_status(buy, buy.fill_status(), market);
+        }
+        for sell in &uncross.sells {
+            self.record_order_status(sell, sell.fill_status(), market);
+        }
+        for (order, cancelled) in &uncross.cancelled {
+            self.unlock_order_funds(order, *cancelled, market);
+            self.update_db_orders(order, order.filled, &Vec::new(), market);
+        }
+        self.record_cancelled(&uncross.cancelled, market);
 
-        Ok((executed_qty, fills, order_id))
+        // One depth update for every level the uncross changed
+        for (before, after, side) in [(bids_before, bids_after, OrderSide::Buy), (asks_before, asks_after, OrderSide::Sell)] {
+            for level in before.iter().filter(|level| !after.contains(level)) {
+                self.send_updated_depth_at(level.0, &side, market);
+            }
+        }
     }
 
-    fn check_and_lock_funds(
-        &mut self,
-        base_asset: &str,
-        quote_asset: &str,
-        side: &OrderSide,
-        user_id: &str,
-        price: &str,
-        quantity: &str,
-    ) -> Result<(), String> {
-        let price = price.parse::<f64>().map_err(|_| "Invalid price")?;
-        let quantity = quantity.parse::<f64>().map_err(|_| "Invalid quantity")?;
+    fn lock_funds(&mut self, user_id: &str, asset: &str, amount: Decimal) -> Result<(), RejectReason> {
+        let asset_balance = self.balances.get_mut(user_id)
+            .and_then(|b| b.get_mut(asset))
+            .ok_or(RejectReason::InsufficientFunds)?;
+        if asset_balance.available < amount {
+            return Err(RejectReason::InsufficientFunds);
+        }
+        asset_balance.available -= amount;
+        asset_balance.locked += amount;
+        Ok(())
+    }
 
-        let balances = self.balances.get_mut(user_id).ok_or("User not found")?;
+    /// Releases what backs `quantity` of a resting order that is cancelled.
+    fn unlock_order_funds(&mut self, order: &Order, quantity: Decimal, market: &str) {
+        let Ok((base_asset, quote_asset)) = self.market_assets(market) else {
+            return;
+        };
+        let (asset, amount) = match order.side {
+            OrderSide::Buy => (quote_asset, quantity * order.price),
+            OrderSide::Sell => (base_asset, quantity),
+        };
+        self.unlock_funds(&order.user_id, &asset, amount);
+    }
 
-        match side {
-            OrderSide::Buy => {
-                let asset_balance = balances.get_mut(quote_asset).ok_or("Asset not found")?;
-                let required_amount = price * quantity;
-                if asset_balance.available < required_amount {
-                    return Err("Insufficient funds".to_string());
-                }
-                asset_balance.available -= required_amount;
-                asset_balance.locked += required_amount;
-            }
-            OrderSide::Sell => {
-                let asset_balance = balances.get_mut(base_asset).ok_or("Asset not found")?;
-                if asset_balance.available < quantity {
-                    return Err("Insufficient funds".to_string());
-                }
-                asset_balance.available -= quantity;
-                asset_balance.locked += quantity;
-            }
+    fn unlock_funds(&mut self, user_id: &str, asset: &str, amount: Decimal) {
+        if let Some(asset_balance) = self.balances.get_mut(user_id).and_then(|b| b.get_mut(asset)) {
+            asset_balance.available += amount;
+            asset_balance.locked -= amount;
         }
-        Ok(())
     }
 
-    fn update_db_orders(&mut self, order: &Order, executed_qty: f64, fills: &Vec<Fill>, market: &str) {
+    /// Locks what a limit order could spend at its limit price: quote for
+    /// buys, base for sells. Returns the amount locked.
+    fn check_and_lock_funds(&mut self, order: &Order, base_asset: &str, quote_asset: &str) -> Result<Decimal, RejectReason> {
+        let (asset, amount) = match order.side {
+            OrderSide::Buy => (
+                quote_asset,
+                order.quantity.checked_mul(order.price).ok_or(RejectReason::NotionalAboveMaximum)?,
+            ),
+            OrderSide::Sell => (base_asset, order.quantity),
+        };
+        self.lock_funds(&order.user_id, asset, amount)?;
+        Ok(amount)
+    }
+
+    fn update_db_orders(&mut self, order: &Order, executed_qty: Decimal, fills: &Vec<Fill>, market: &str) {
         let conn = RedisManager::get_instance().lock().unwrap();
         let message = DbMessage::OrderUpdate(OrderMessage {
             order_id: order.order_id.clone(),
-            executed_qty,
+            executed_qty: executed_qty.to_string(),
             market: Some(market.to_string()),
             price: Some(order.price.to_string()),
             quantity: Some(order.quantity.to_string()),
@@ -354,7 +1437,7 @@ impl Engine {
         for fill in fills {
             if let Err(e) = conn.push_message_to_db_processor(DbMessage::OrderUpdate(OrderMessage {
                 order_id: fill.marker_order_id.clone(),
-                executed_qty: fill.qty,
+                executed_qty: fill.qty.to_string(),
                 market: None,
                 price: None,
                 quantity: None,
@@ -365,22 +1448,87 @@ impl Engine {
         }
     }
 
-    fn create_db_trades(&mut self, fills: &Vec<Fill>, market: &str, user_id: &str) {
+    /// Sends the current status and fills of an order to the db processor,
+    /// which keeps its latest state and the history of its transitions.
+    fn record_order_status(&mut self, order: &Order, status: OrderStatus, market: &str) {
+        if let (false, Some(client_order_id)) = (status.is_open(), &order.client_order_id) {
+            self.forget_client_order(&order.user_id, market, client_order_id);
+        }
+        let message = DbMessage::OrderStatusChanged(OrderStatusMessage {
+            order_id: order.order_id.clone(),
+            client_order_id: order.client_order_id.clone(),
+            user_id: order.user_id.clone(),
+            market: market.to_string(),
+            side: order.side.clone(),
+            price: order.price.to_string(),
+            quantity: order.quantity.to_string(),
+            executed_qty: order.filled.to_string(),
+            status,
+            timestamp: now_millis(),
+        });
+        if let Err(e) = RedisManager::get_instance().lock().unwrap().push_message_to_db_processor(message) {
+            info!("Failed to push order status: {}", e);
+        }
+    }
+
+    /// Frees a client order id once its order is done.
+    fn forget_client_order(&mut self, user_id: &str, market: &str, client_order_id: &str) {
+        let Some(markets) = self.client_orders.get_mut(user_id) else {
+            return;
+        };
+        if let Some(orders) = markets.get_mut(market) {
+            orders.remove(client_order_id);
+            if orders.is_empty() {
+                markets.remove(market);
+            }
+        }
+        if markets.is_empty() {
+            self.client_orders.remove(user_id);
+        }
+    }
+
+    /// Records orders reduced by self-trade prevention: cancelled if nothing
+    /// of them is left on the book, otherwise as they now stand.
+    fn record_cancelled(&mut self, reduced: &[(Order, Decimal)], market: &str) {
+        let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) else {
+            return;
+        };
+        let statuses: Vec<(Order, OrderStatus)> = reduced.iter()
+            .map(|(order, _)| match orderbook.get_order(&order.order_id) {
+                Some(open) => (open.clone(), open.fill_status()),
+                None => (order.clone(), OrderStatus::Cancelled),
+            })
+            .collect();
+        for (order, status) in statuses {
+            self.record_order_status(&order, status, market);
+        }
+    }
+
+    fn create_db_trades(&mut self, fills: &Vec<Fill>, market: &str, user_id: &str, side: &OrderSide) {
         info!("Fills, {:?}", fills);
         for fill in fills {
             let conn = RedisManager::get_instance().lock().unwrap();
             let quote_qty = fill.qty * fill.price;
+            let taker = (user_id.to_string(), fill.fee.to_string(), fill.fee_asset.clone());
+            let maker = (fill.other_user_id.clone(), fill.maker_fee.to_string(), fill.maker_fee_asset.clone());
+            let ((buyer_id, buyer_fee, buyer_fee_asset), (seller_id, seller_fee, seller_fee_asset)) = match side {
+                OrderSide::Buy => (taker, maker),
+                OrderSide::Sell => (maker, taker),
+            };
             let message = DbMessage::TradeAdded(TradeMessage {
                 id: fill.trade_id.to_string(),
-                is_buyer_maker: fill.other_user_id == user_id,
+                is_buyer_maker: *side == OrderSide::Sell,
                 price: fill.price.to_string(),
                 quantity: fill.qty.to_string(),
                 quote_quantity: quote_qty.to_string(),
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64,
+                timestamp: now_millis(),
                 market: market.to_string(),
+                buyer_id,
+                seller_id,
+                buyer_fee,
+                buyer_fee_asset,
+                seller_fee,
+                seller_fee_asset,
             });
 
             if let Err(e) = conn.push_message_to_db_processor(message) {
@@ -410,13 +1558,14 @@ impl Engine {
         }
     }
 
-    fn publish_ws_depth_updates(&mut self, fills: &Vec<Fill>, price: String, side: &OrderSide, market: &str) {
+    fn publish_ws_depth_updates(&mut self, fills: &Vec<Fill>, price: Decimal, side: &OrderSide, market: &str) {
         let orderbook = match self.orderbooks.iter().find(|o| o.ticker() == market) {
             Some(ob) => ob,
             None => return,
         };
 
         let depth = orderbook.get_depth();
+        let price = price.to_string();
         let fill_prices: Vec<String> = fills.iter().map(|f| f.price.to_string()).collect();
 
         let message = match side {
@@ -474,26 +1623,94 @@ impl Engine {
             println!("Failed to publish depth update: {}", e);
         }
     }
-    
-    #[allow(dead_code)]
-    fn send_updated_depth_at(&mut self, price: f64, market: &str) {
+
+    fn publish_auction(&self, market: &str, equilibrium: Option<auction::Equilibrium>, ends_at: i64) {
+        let stream = format!("auction@{}", market);
+        let message = WsMessage {
+            stream: stream.clone(),
+            data: WsMessageData::Auction(AuctionData {
+                e: "auction".to_string(),
+                s: market.to_string(),
+                p: equilibrium.map(|eq| eq.price.to_string()),
+                q: equilibrium.map(|eq| eq.volume.to_string()),
+                end: ends_at,
+            }),
+        };
+
+        if let Err(e) = RedisManager::get_instance().lock().unwrap().publish_message_to_ws(&stream, message) {
+            info!("Failed to publish auction update: {}", e);
+        }
+    }
+
+    fn publish_market_status(&self, market: &Market) {
+        let stream = format!("status@{}", market.symbol);
+        let message = WsMessage {
+            stream: stream.clone(),
+            data: WsMessageData::Status(StatusData {
+                e: "status".to_string(),
+                s: market.symbol.clone(),
+                status: market.status,
+            }),
+        };
+
+        if let Err(e) = RedisManager::get_instance().lock().unwrap().publish_message_to_ws(&stream, message) {
+            info!("Failed to publish market status: {}", e);
+        }
+    }
+
+    fn publish_order_update(&self, order: &Order, status: OrderStatus, market: &str) {
+        let stream = format!("order@{}", order.user_id);
+        let message = WsMessage {
+            stream: stream.clone(),
+            data: WsMessageData::OrderUpdate(OrderUpdateData {
+                e: "order".to_string(),
+                s: market.to_string(),
+                i: order.order_id.clone(),
+                status,
+                q: order.quantity.to_string(),
+                z: order.filled.to_string(),
+            }),
+        };
+
+        if let Err(e) = RedisManager::get_instance().lock().unwrap().publish_message_to_ws(&stream, message) {
+            info!("Failed to publish order update: {}", e);
+        }
+    }
+
+    /// Publishes the current size of one price level, or `0` once the level
+    /// is gone.
+    fn send_updated_depth_at(&mut self, price: Decimal, side: &OrderSide, market: &str) {
+        match side {
+            OrderSide::Buy => self.send_updated_depth_levels(&[price], &[], market),
+            OrderSide::Sell => self.send_updated_depth_levels(&[], &[price], market),
+        }
+    }
+
+    /// Publishes the current size of several price levels in one depth
+    /// update.
+    fn send_updated_depth_levels(&mut self, bid_prices: &[Decimal], ask_prices: &[Decimal], market: &str) {
         let orderbook = match self.orderbooks.iter().find(|o| o.ticker() == market) {
             Some(ob) => ob,
             None => return,
         };
 
-        let price_str = price.to_string();
         let depth = orderbook.get_depth();
-        let updated_bids: Vec<[String; 2]> = depth.bids
-            .iter()
-            .filter(|(p, _)| *p == price_str)
-            .map(|(p, q)| [p.clone(), q.clone()])
-            .collect();
-        let updated_asks: Vec<[String; 2]> = depth.asks
-            .iter()
-            .filter(|(p, _)| *p == price_str)
-            .map(|(p, q)| [p.clone(), q.clone()])
-            .collect();
+        let updated_levels = |levels: &[(String, String)], prices: &[Decimal]| -> Vec<[String; 2]> {
+            let mut prices = prices.to_vec();
+            prices.sort();
+            prices.dedup();
+            prices.iter()
+                .map(|price| {
+                    let price_str = price.to_string();
+                    levels.iter()
+                        .find(|(p, _)| *p == price_str)
+                        .map(|(p, q)| [p.clone(), q.clone()])
+                        .unwrap_or_else(|| [price_str, "0".to_string()])
+                })
+                .collect()
+        };
+        let updated_bids = updated_levels(&depth.bids, bid_prices);
+        let updated_asks = updated_levels(&depth.asks, ask_prices);
 
         let message = WsMessage {
             stream: format!("depth@{}", market),
@@ -510,6 +1727,78 @@ impl Engine {
         }
     }
 
+    /// Works out the fee on each fill, charged on what each side receives:
+    /// base for the buyer, quote for the seller. A maker rebate is paid in
+    /// the asset the taker's fee was collected in, so the fee account never
+    /// pays out more of an asset than the fill brought in. Auction trades
+    /// have no resting side, so both sides pay the taker rate.
+    fn charge_fees(&self, fills: &mut [Fill], taker_side: &OrderSide, market: &str, auction: bool) {
+        let Ok((base_asset, quote_asset)) = self.market_assets(market) else {
+            return;
+        };
+        let Some(spec) = self.orderbooks.iter().find(|o| o.ticker() == market).map(|o| o.spec()) else {
+            return;
+        };
+        let (Some(base), Some(quote)) = (self.assets.get(&base_asset), self.assets.get(&quote_asset)) else {
+            return;
+        };
+        let taker_rate = spec.taker_fee_rate;
+        let maker_rate = if auction { taker_rate } else { spec.maker_fee_rate };
+
+        for fill in fills {
+            let quote_qty = fill.qty * fill.price;
+            match taker_side {
+                OrderSide::Buy => {
+                    fill.fee = base.truncate(fill.qty * taker_rate);
+                    fill.fee_asset = base_asset.clone();
+                    if maker_rate < Decimal::ZERO {
+                        fill.maker_fee = base.truncate(fill.qty * maker_rate);
+                        fill.maker_fee_asset = base_asset.clone();
+                    } else {
+                        fill.maker_fee = quote.truncate(quote_qty * maker_rate);
+                        fill.maker_fee_asset = quote_asset.clone();
+                    }
+                }
+                OrderSide::Sell => {
+                    fill.fee = quote.truncate(quote_qty * taker_rate);
+                    fill.fee_asset = quote_asset.clone();
+                    if maker_rate < Decimal::ZERO {
+                        fill.maker_fee = quote.truncate(quote_qty * maker_rate);
+                        fill.maker_fee_asset = quote_asset.clone();
+                    } else {
+                        fill.maker_fee = base.truncate(fill.qty * maker_rate);
+                        fill.maker_fee_asset = base_asset.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Credits a fee to the fee account, or pays a rebate (a negative fee)
+    /// out of it.
+    fn collect_fee(&mut self, asset: &str, amount: Decimal) {
+        if amount == Decimal::ZERO {
+            return;
+        }
+        let fee_balance = self.balances
+            .entry(FEE_ACCOUNT.to_string())
+            .or_default()
+            .entry(asset.to_string())
+            .or_insert(UserBalance { available: Decimal::ZERO, locked: Decimal::ZERO });
+        fee_balance.available += amount;
+    }
+
+    /// Takes the resting order's fee out of its owner's balance, or pays
+    /// their rebate into it.
+    fn charge_maker_fee(&mut self, fill: &Fill) {
+        if let Some(fee_balance) = self.balances
+            .get_mut(&fill.other_user_id)
+            .and_then(|balance| balance.get_mut(&fill.maker_fee_asset))
+        {
+            fee_balance.available -= fill.maker_fee;
+        }
+    }
+
     fn update_balance(
         &mut self,
         user_id: &str,
@@ -517,8 +1806,8 @@ impl Engine {
         quote_asset: &str,
         side: &OrderSide,
         fills: &Vec<Fill>,
-        _executed_qty: f64,
-    ) -> Result<(), String> {
+        _executed_qty: Decimal,
+    ) {
         match side {
             OrderSide::Buy => {
                 for fill in fills {
@@ -546,9 +1835,13 @@ impl Engine {
                     // Update base asset balance for buyer
                     if let Some(user_balance) = self.balances.get_mut(user_id) {
                         if let Some(base_balance) = user_balance.get_mut(base_asset) {
-                            base_balance.available += fill.qty;
+                            base_balance.available += fill.qty - fill.fee;
                         }
                     }
+
+                    self.charge_maker_fee(fill);
+                    self.collect_fee(&fill.fee_asset, fill.fee);
+                    self.collect_fee(&fill.maker_fee_asset, fill.maker_fee);
                 }
             },
             OrderSide::Sell => {
@@ -563,7 +1856,7 @@ impl Engine {
                     // Update quote asset balance for seller
                     if let Some(user_balance) = self.balances.get_mut(user_id) {
                         if let Some(quote_balance) = user_balance.get_mut(quote_asset) {
-                            quote_balance.available += fill.qty * fill.price;
+                            quote_balance.available += fill.qty * fill.price - fill.fee;
                         }
                     }
 
@@ -580,30 +1873,636 @@ impl Engine {
                             base_balance.locked -= fill.qty;
                         }
                     }
+
+                    self.charge_maker_fee(fill);
+                    self.collect_fee(&fill.fee_asset, fill.fee);
+                    self.collect_fee(&fill.maker_fee_asset, fill.maker_fee);
                 }
             }
         }
-        Ok(())
     }
 
-    fn on_ramp(&mut self, user_id: &str, amount: f64) {
-        if let Some(user_balance) = self.balances.get_mut(user_id) {
-            if let Some(base_balance) = user_balance.get_mut(BASE_CURRENCY) {
-                base_balance.available += amount;
-            } else {
-                user_balance.insert(BASE_CURRENCY.to_string(), UserBalance {
-                    available: amount,
-                    locked: 0.0,
-                });
-            }
-        } else {
-            let mut new_balance = HashMap::new();
-            new_balance.insert(BASE_CURRENCY.to_string(), UserBalance {
-                available: amount,
-                locked: 0.0,
-            });
-            self.balances.insert(user_id.to_string(), new_balance);
+    /// Credits a deposit of a listed asset to a user's available balance.
+    fn on_ramp(&mut self, user_id: &str, asset: &str, amount: Decimal) -> Result<(), RejectReason> {
+        let listed = self.assets.get(asset).ok_or(RejectReason::UnknownAsset)?;
+        if !amount.is_positive() || !listed.fits(amount) {
+            return Err(RejectReason::InvalidAmount);
         }
+        self.balances
+            .entry(user_id.to_string())
+            .or_default()
+            .entry(asset.to_string())
+            .or_insert(UserBalance { available: Decimal::ZERO, locked: Decimal::ZERO })
+            .available += amount;
+        Ok(())
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> Engine {
+        Engine::with_markets(MarketConfig::defaults())
+    }
+
+    fn limit_buy(market: &str) -> CreateOrderData {
+        serde_json::from_value(serde_json::json!({
+            "market": market,
+            "price": "10",
+            "quantity": "1",
+            "side": "buy",
+        })).unwrap()
+    }
+
+    fn order(side: OrderSide, price: &str, quantity: &str) -> CreateOrderData {
+        let mut order = limit_buy("SOL_USDC");
+        order.side = side;
+        order.price = Some(price.to_string());
+        order.quantity = quantity.to_string();
+        order
+    }
+
+    /// Everything held of `asset`, available or locked, across all accounts
+    /// including the fee account.
+    fn total(engine: &Engine, asset: &str) -> Decimal {
+        engine.balances.values()
+            .filter_map(|balances| balances.get(asset))
+            .map(|balance| balance.available + balance.locked)
+            .sum()
+    }
+
+    fn assert_no_negative_locks(engine: &Engine) {
+        assert!(engine.balances.values().flat_map(|b| b.values()).all(|b| b.locked >= Decimal::ZERO));
+    }
+
+    fn with_fees(mut config: MarketConfig) -> Engine {
+        config.spec.taker_fee_rate = "0.0025".parse().unwrap();
+        config.spec.maker_fee_rate = "-0.0005".parse().unwrap();
+        let mut engine = Engine::with_markets(vec![config]);
+        for user_id in ["user1", "user2", "user3"] {
+            engine.ensure_user_balance(user_id);
+        }
+        engine
+    }
+
+    #[test]
+    fn test_market_status_gates_orders_and_cancels() {
+        let mut engine = engine();
+        assert_eq!(engine.create_order(&limit_buy("ETH_USDC"), "user1").err(), Some(RejectReason::UnknownMarket));
+
+        engine.set_market_status("SOL_USDC", MarketStatus::CancelOnly, 0).unwrap();
+        assert_eq!(engine.create_order(&limit_buy("SOL_USDC"), "user1").err(), Some(RejectReason::MarketNotTrading));
+        assert_eq!(engine.cancel_order("missing", "SOL_USDC", "user1").err(), Some(RejectReason::OrderNotFound));
+
+        engine.set_market_status("SOL_USDC", MarketStatus::Halted, 0).unwrap();
+        assert_eq!(engine.cancel_order("missing", "SOL_USDC", "user1").err(), Some(RejectReason::MarketHalted));
+        assert_eq!(engine.set_market_status("ETH_USDC", MarketStatus::Halted, 0).err(), Some(RejectReason::UnknownMarket));
+    }
+
+    #[test]
+    fn test_price_band_rejects_far_limit_orders() {
+        let mut config = MarketConfig::defaults().remove(0);
+        config.spec.price_band_percent = Some(Decimal::from_int(10));
+        config.spec.reference_price = Some(Decimal::from_int(10));
+        let mut engine = Engine::with_markets(vec![config]);
+
+        let mut order = limit_buy("SOL_USDC");
+        order.price = Some("11.01".to_string());
+        assert_eq!(engine.create_order(&order, "user1").err(), Some(RejectReason::PriceOutsideBand));
+        order.price = Some("8.99".to_string());
+        assert_eq!(engine.create_order(&order, "user1").err(), Some(RejectReason::PriceOutsideBand));
+    }
+
+    #[test]
+    fn test_tick_resumes_market_after_timed_halt() {
+        let mut engine = engine();
+        engine.set_market_status("SOL_USDC", MarketStatus::Halted, 0).unwrap();
+        engine.timed_halts.insert("SOL_USDC".to_string(), 1_000);
+
+        engine.tick(999);
+        assert_eq!(engine.markets["SOL_USDC"].status, MarketStatus::Halted);
+        engine.tick(1_000);
+        assert_eq!(engine.markets["SOL_USDC"].status, MarketStatus::Trading);
+        assert!(engine.timed_halts.is_empty());
+
+        // A halt set by an admin is not lifted by the timer
+        engine.timed_halts.insert("SOL_USDC".to_string(), 2_000);
+        engine.set_market_status("SOL_USDC", MarketStatus::Halted, 0).unwrap();
+        engine.tick(2_000);
+        assert_eq!(engine.markets["SOL_USDC"].status, MarketStatus::Halted);
+    }
+
+    #[test]
+    fn test_opening_auction_uncrosses_at_single_price() {
+        let mut config = MarketConfig::defaults().remove(0);
+        config.spec.auction_secs = Some(60);
+        let mut engine = Engine::with_markets(vec![config]);
+        assert_eq!(engine.markets["SOL_USDC"].status, MarketStatus::Auction);
+
+        let order = |side: &str, price: &str, quantity: &str| -> CreateOrderData {
+            serde_json::from_value(serde_json::json!({
+                "market": "SOL_USDC", "price": price, "quantity": quantity, "side": side,
+            })).unwrap()
+        };
+        let mut market_buy = order("buy", "10", "1");
+        market_buy.order_type = OrderType::Market;
+        assert_eq!(engine.create_order(&market_buy, "user1").err(), Some(RejectReason::NotAllowedInAuction));
+
+        let buy = engine.create_order(&order("buy", "10.2", "3"), "user1").unwrap();
+        engine.create_order(&order("buy", "10.1", "2"), "user2").unwrap();
+        let sell = engine.create_order(&order("sell", "9.8", "2"), "user3").unwrap();
+        engine.create_order(&order("sell", "10.1", "4"), "user4").unwrap();
+        assert!(buy.fills.is_empty() && sell.fills.is_empty());
+
+        let ends_at = engine.auctions["SOL_USDC"].ends_at;
+        engine.tick(ends_at - 1);
+        let indicative = engine.auctions["SOL_USDC"].indicative.unwrap();
+        assert_eq!(indicative.price, "10.1".parse().unwrap());
+        assert_eq!(indicative.volume, Decimal::from_int(5));
+
+        engine.tick(ends_at);
+        assert!(engine.auctions.is_empty());
+        assert_eq!(engine.markets["SOL_USDC"].status, MarketStatus::Trading);
+        let orderbook = &engine.orderbooks[0];
+        assert_eq!(orderbook.last_price(), "10.1".parse().unwrap());
+        assert!(orderbook.get_order(&buy.order_id).is_none());
+        assert_eq!(orderbook.get_order(&sell.order_id).map(|o| o.filled), None);
+        assert_eq!(engine.balances["user3"]["USDC"].available, Decimal::from_int(10_000_000) + "20.2".parse::<Decimal>().unwrap());
+    }
+
+    #[test]
+    fn test_stops_wait_for_auction_to_uncross() {
+        let mut config = MarketConfig::defaults().remove(0);
+        config.spec.auction_secs = Some(60);
+        let mut engine = Engine::with_markets(vec![config]);
+        let now = now_millis();
+        engine.set_market_status("SOL_USDC", MarketStatus::Trading, now).unwrap();
+        engine.tick(now);
+        engine.create_order(&order(OrderSide::Sell, "10", "1"), "user1").unwrap();
+        engine.create_order(&order(OrderSide::Buy, "10", "1"), "user2").unwrap();
+        assert_eq!(engine.orderbooks[0].last_price(), Decimal::from_int(10));
+
+        // Resuming from a halt reopens with an auction
+        engine.set_market_status("SOL_USDC", MarketStatus::Halted, now).unwrap();
+        engine.set_market_status("SOL_USDC", MarketStatus::Trading, now).unwrap();
+        assert_eq!(engine.markets["SOL_USDC"].status, MarketStatus::Auction);
+
+        // The stop price has already been crossed, but nothing triggers mid-auction
+        let mut stop = order(OrderSide::Buy, "0", "1");
+        stop.order_type = OrderType::StopMarket;
+        stop.price = None;
+        stop.stop_price = Some("9".to_string());
+        let stop = engine.create_order(&stop, "user3").unwrap();
+        assert!(engine.trigger_books["SOL_USDC"].get_order(&stop.order_id).is_some());
+        assert_eq!(engine.balances["user3"]["USDC"].locked, Decimal::from_int(9));
+        engine.create_order(&order(OrderSide::Sell, "9", "2"), "user1").unwrap();
+
+        let ends_at = engine.auctions["SOL_USDC"].ends_at;
+        engine.tick(ends_at);
+        assert!(engine.trigger_books["SOL_USDC"].get_order(&stop.order_id).is_none());
+        assert_eq!(engine.balances["user3"]["SOL"].available, Decimal::from_int(10_000_001));
+        assert_eq!(engine.balances["user3"]["USDC"].locked, Decimal::ZERO);
+        assert_no_negative_locks(&engine);
+    }
+
+    #[test]
+    fn test_leaving_auction_for_cancel_only_then_trading_matches_again() {
+        let mut config = MarketConfig::defaults().remove(0);
+        config.spec.auction_secs = Some(60);
+        let mut engine = Engine::with_markets(vec![config]);
+        assert_eq!(engine.markets["SOL_USDC"].status, MarketStatus::Auction);
+        let buy = engine.create_order(&order(OrderSide::Buy, "10", "1"), "user1").unwrap();
+        engine.create_order(&order(OrderSide::Sell, "9.9", "1"), "user2").unwrap();
+
+        let now = now_millis();
+        engine.set_market_status("SOL_USDC", MarketStatus::CancelOnly, now).unwrap();
+        assert!(engine.auctions.is_empty());
+        engine.set_market_status("SOL_USDC", MarketStatus::Trading, now).unwrap();
+        assert_eq!(engine.markets["SOL_USDC"].status, MarketStatus::Trading);
+        // What the auction left crossed trades when the market reopens
+        assert!(engine.orderbooks[0].get_order(&buy.order_id).is_none());
+
+        engine.create_order(&order(OrderSide::Sell, "10", "1"), "user2").unwrap();
+        let placed = engine.create_order(&order(OrderSide::Buy, "10", "1"), "user3").unwrap();
+        assert_eq!(placed.status, OrderStatus::Filled);
+        assert_no_negative_locks(&engine);
+    }
+
+    #[test]
+    fn test_batch_auction_clears_on_interval() {
+        let mut config = MarketConfig::defaults().remove(0);
+        config.spec.matching = MatchingMode::BatchAuction { interval_ms: 1_000 };
+        let mut engine = Engine::with_markets(vec![config]);
+        engine.tick(0);
+
+        let mut buy = limit_buy("SOL_USDC");
+        buy.quantity = "2".to_string();
+        let buy = engine.create_order(&buy, "user1").unwrap();
+        let mut sell = limit_buy("SOL_USDC");
+        sell.side = OrderSide::Sell;
+        sell.price = Some("9.9".to_string());
+        engine.create_order(&sell, "user2").unwrap();
+        assert!(buy.fills.is_empty());
+
+        engine.tick(999);
+        assert_eq!(engine.orderbooks[0].get_order(&buy.order_id).unwrap().filled, Decimal::ZERO);
+        engine.tick(1_000);
+        assert_eq!(engine.orderbooks[0].get_order(&buy.order_id).unwrap().filled, Decimal::from_int(1));
+        // Both sides' levels are candidates; 9.9 and 10 tie, so the lower wins
+        assert_eq!(engine.orderbooks[0].last_price(), "9.9".parse().unwrap());
+        assert_eq!(engine.next_batches["SOL_USDC"], 2_000);
+    }
+
+    #[test]
+    fn test_fees_come_off_what_each_side_receives() {
+        let mut config = MarketConfig::defaults().remove(0);
+        config.spec.taker_fee_rate = "0.002".parse().unwrap();
+        config.spec.maker_fee_rate = "-0.001".parse().unwrap();
+        let mut engine = Engine::with_markets(vec![config]);
+
+        let mut sell = limit_buy("SOL_USDC");
+        sell.side = OrderSide::Sell;
+        sell.quantity = "2".to_string();
+        engine.create_order(&sell, "maker").unwrap();
+        let mut buy = limit_buy("SOL_USDC");
+        buy.quantity = "2".to_string();
+        let placed = engine.create_order(&buy, "taker").unwrap();
+
+        let fill = &placed.fills[0];
+        assert_eq!((fill.fee, fill.fee_asset.as_str()), ("0.004".parse().unwrap(), "SOL"));
+        // The rebate comes out of the SOL the taker paid
+        assert_eq!((fill.maker_fee, fill.maker_fee_asset.as_str()), ("-0.002".parse().unwrap(), "SOL"));
+
+        let start = Decimal::from_int(10_000_000);
+        assert_eq!(engine.balances["taker"]["SOL"].available, start + "1.996".parse::<Decimal>().unwrap());
+        assert_eq!(engine.balances["maker"]["USDC"].available, start + Decimal::from_int(20));
+        assert_eq!(engine.balances["maker"]["SOL"].available, start - "1.998".parse::<Decimal>().unwrap());
+        assert_eq!(engine.balances[FEE_ACCOUNT]["SOL"].available, "0.002".parse().unwrap());
+        assert!(!engine.balances[FEE_ACCOUNT].contains_key("USDC"));
+    }
+
+    #[test]
+    fn test_good_till_date_order_expires_on_tick() {
+        let mut engine = engine();
+        let now = now_millis();
+        let mut order = limit_buy("SOL_USDC");
+        order.expires_at = Some(now + 60_000);
+        let placed = engine.create_order(&order, "user1").unwrap();
+        assert!(engine.orderbooks[0].get_order(&placed.order_id).is_some());
+
+        engine.tick(now + 59_999);
+        assert!(engine.orderbooks[0].get_order(&placed.order_id).is_some());
+        engine.tick(now + 60_000);
+        assert!(engine.orderbooks[0].get_order(&placed.order_id).is_none());
+        assert!(engine.expiries.is_empty());
+    }
+
+    #[test]
+    fn test_expiry_needs_a_future_time_and_a_resting_order() {
+        let mut engine = engine();
+        let mut order = limit_buy("SOL_USDC");
+        order.expires_at = Some(now_millis() - 1);
+        assert_eq!(engine.create_order(&order, "user1").err(), Some(RejectReason::InvalidExpiry));
+
+        order.expires_at = Some(now_millis() + 60_000);
+        order.time_in_force = TimeInForce::Ioc;
+        assert_eq!(engine.create_order(&order, "user1").err(), Some(RejectReason::InvalidExpiry));
+    }
+
+    #[test]
+    fn test_cancel_all_orders_filters_by_side_and_user() {
+        let mut engine = engine();
+        let first = engine.create_order(&limit_buy("SOL_USDC"), "user1").unwrap();
+        let mut second = limit_buy("SOL_USDC");
+        second.price = Some("9".to_string());
+        let second = engine.create_order(&second, "user1").unwrap();
+        let mut sell = limit_buy("SOL_USDC");
+        sell.side = OrderSide::Sell;
+        sell.price = Some("11".to_string());
+        let sell = engine.create_order(&sell, "user1").unwrap();
+        let other = engine.create_order(&limit_buy("SOL_USDC"), "user2").unwrap();
+
+        let cancelled = engine.cancel_all_orders("user1", Some("SOL_USDC"), Some(&OrderSide::Buy)).unwrap();
+        assert_eq!(cancelled, vec![second.order_id, first.order_id]);
+        assert!(engine.orderbooks[0].get_order(&sell.order_id).is_some());
+        assert!(engine.orderbooks[0].get_order(&other.order_id).is_some());
+
+        assert_eq!(engine.cancel_all_orders("user1", None, None).unwrap(), vec![sell.order_id]);
+        assert!(engine.cancel_all_orders("user1", None, None).unwrap().is_empty());
+        assert_eq!(engine.cancel_all_orders("user1", Some("ETH_USDC"), None).err(), Some(RejectReason::UnknownMarket));
+    }
+
+    fn amend(order_id: &str, price: Option<&str>, quantity: Option<&str>) -> AmendOrderData {
+        AmendOrderData {
+            order_id: order_id.to_string(),
+            market: "SOL_USDC".to_string(),
+            price: price.map(str::to_string),
+            quantity: quantity.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_amend_size_decrease_keeps_queue_position() {
+        let mut engine = engine();
+        let first = engine.create_order(&order(OrderSide::Buy, "10", "2"), "user1").unwrap();
+        engine.create_order(&order(OrderSide::Buy, "10", "1"), "user2").unwrap();
+
+        let amended = engine.amend_order(&amend(&first.order_id, None, Some("1")), "user1").unwrap();
+        assert_eq!(amended.quantity, Decimal::from_int(1));
+        assert_eq!(engine.balances["user1"]["USDC"].locked, Decimal::from_int(10));
+
+        let sell = engine.create_order(&order(OrderSide::Sell, "10", "1"), "user3").unwrap();
+        assert_eq!(sell.fills[0].other_user_id, "user1");
+    }
+
+    #[test]
+    fn test_amend_price_change_relocks_funds_and_moves_depth() {
+        let mut engine = engine();
+        let placed = engine.create_order(&order(OrderSide::Buy, "10", "2"), "user1").unwrap();
+
+        engine.amend_order(&amend(&placed.order_id, Some("9"), None), "user1").unwrap();
+        assert_eq!(engine.balances["user1"]["USDC"].locked, Decimal::from_int(18));
+        assert_eq!(engine.orderbooks[0].get_depth().bids, vec![("9".to_string(), "2".to_string())]);
+
+        engine.amend_order(&amend(&placed.order_id, Some("11"), Some("3")), "user1").unwrap();
+        assert_eq!(engine.balances["user1"]["USDC"].locked, Decimal::from_int(33));
+        assert_eq!(engine.orderbooks[0].get_depth().bids, vec![("11".to_string(), "3".to_string())]);
+    }
+
+    #[test]
+    fn test_amend_without_funds_leaves_order_unchanged() {
+        let mut engine = engine();
+        let placed = engine.create_order(&limit_buy("SOL_USDC"), "user1").unwrap();
+        engine.balances.get_mut("user1").unwrap().get_mut("USDC").unwrap().available = Decimal::from_int(5);
+        let before = engine.balances["user1"]["USDC"].clone();
+
+        let result = engine.amend_order(&amend(&placed.order_id, None, Some("2")), "user1");
+        assert_eq!(result.err(), Some(RejectReason::InsufficientFunds));
+        assert_eq!(engine.orderbooks[0].get_order(&placed.order_id).unwrap().quantity, Decimal::from_int(1));
+        assert_eq!(engine.balances["user1"]["USDC"].available, before.available);
+        assert_eq!(engine.balances["user1"]["USDC"].locked, before.locked);
+    }
+
+    #[test]
+    fn test_amend_rejects_other_users_orders() {
+        let mut engine = engine();
+        let placed = engine.create_order(&limit_buy("SOL_USDC"), "user1").unwrap();
+
+        let result = engine.amend_order(&amend(&placed.order_id, None, Some("0.5")), "user2");
+        assert_eq!(result.err(), Some(RejectReason::OrderNotFound));
+        assert_eq!(engine.orderbooks[0].get_order(&placed.order_id).unwrap().quantity, Decimal::from_int(1));
+    }
+
+    #[test]
+    fn test_dead_man_switch_cancels_orders_unless_refreshed() {
+        let mut engine = engine();
+        let placed = engine.create_order(&limit_buy("SOL_USDC"), "user1").unwrap();
+
+        assert_eq!(engine.set_dead_man_switch("user1", 5_000, 0), Some(5_000));
+        engine.tick(4_000);
+        assert_eq!(engine.set_dead_man_switch("user1", 5_000, 4_000), Some(9_000));
+        engine.tick(8_999);
+        assert!(engine.orderbooks[0].get_order(&placed.order_id).is_some());
+        engine.tick(9_000);
+        assert!(engine.orderbooks[0].get_order(&placed.order_id).is_none());
+        assert!(engine.dead_man_switches.is_empty());
+
+        let placed = engine.create_order(&limit_buy("SOL_USDC"), "user1").unwrap();
+        engine.set_dead_man_switch("user1", 5_000, 10_000);
+        assert_eq!(engine.set_dead_man_switch("user1", 0, 11_000), None);
+        engine.tick(20_000);
+        assert!(engine.orderbooks[0].get_order(&placed.order_id).is_some());
+    }
+
+    #[test]
+    fn test_dead_man_switch_cancels_orders_in_halted_markets() {
+        let mut engine = engine();
+        let placed = engine.create_order(&limit_buy("SOL_USDC"), "user1").unwrap();
+        engine.set_dead_man_switch("user1", 5_000, 0);
+        engine.set_market_status("SOL_USDC", MarketStatus::Halted, 0).unwrap();
+
+        engine.tick(5_000);
+        assert!(engine.orderbooks[0].get_order(&placed.order_id).is_none());
+        assert_eq!(engine.balances["user1"]["USDC"].locked, Decimal::ZERO);
+        assert!(engine.dead_man_switches.is_empty());
+
+        // Nothing is left to trade once the halt is over
+        engine.set_market_status("SOL_USDC", MarketStatus::Trading, 6_000).unwrap();
+        assert!(engine.orderbooks[0].get_open_orders("user1").is_empty());
+    }
+
+    #[test]
+    fn test_client_order_id_makes_placement_idempotent() {
+        let mut engine = engine();
+        let mut order = limit_buy("SOL_USDC");
+        order.client_order_id = Some("quote-1".to_string());
+        let first = engine.create_order(&order, "user1").unwrap();
+        let replay = engine.create_order(&order, "user1").unwrap();
+        assert_eq!(replay.order_id, first.order_id);
+        assert_eq!(engine.orderbooks[0].get_open_orders("user1").len(), 1);
+
+        // Ids are per user
+        let other = engine.create_order(&order, "user2").unwrap();
+        assert_ne!(other.order_id, first.order_id);
+
+        let order_id = engine.resolve_order_id("user1", "SOL_USDC", None, Some("quote-1")).unwrap();
+        assert_eq!(engine.get_order(&order_id, "SOL_USDC", "user1").unwrap().client_order_id.as_deref(), Some("quote-1"));
+        assert_eq!(engine.get_order(&order_id, "SOL_USDC", "user2").err(), Some(RejectReason::OrderNotFound));
+        assert_eq!(engine.resolve_order_id("user1", "SOL_USDC", None, Some("missing")).err(), Some(RejectReason::OrderNotFound));
+
+        // Ids are per market too
+        assert_eq!(engine.resolve_order_id("user1", "ETH_USDC", None, Some("quote-1")).err(), Some(RejectReason::OrderNotFound));
+
+        // A cancelled order frees its id
+        engine.cancel_order(&order_id, "SOL_USDC", "user1").unwrap();
+        assert_eq!(engine.resolve_order_id("user1", "SOL_USDC", None, Some("quote-1")).err(), Some(RejectReason::OrderNotFound));
+        assert!(!engine.client_orders.contains_key("user1"));
+        let again = engine.create_order(&order, "user1").unwrap();
+        assert_ne!(again.order_id, first.order_id);
+    }
+
+    #[test]
+    fn test_client_order_replay_reports_current_state() {
+        let mut engine = engine();
+        let mut buy = order(OrderSide::Buy, "10", "2");
+        buy.client_order_id = Some("quote-1".to_string());
+        let first = engine.create_order(&buy, "user1").unwrap();
+        engine.create_order(&order(OrderSide::Sell, "10", "1"), "user2").unwrap();
+
+        let replay = engine.create_order(&buy, "user1").unwrap();
+        assert_eq!(replay.order_id, first.order_id);
+        assert_eq!(replay.status, OrderStatus::PartiallyFilled);
+        assert_eq!(replay.executed_qty, Decimal::from_int(1));
+
+        // Once filled the order is done and its id no longer replays
+        engine.create_order(&order(OrderSide::Sell, "10", "1"), "user2").unwrap();
+        assert!(engine.client_orders.is_empty());
+        let restored = Engine::restore(engine.snapshot()).unwrap();
+        assert!(restored.client_orders.is_empty());
+    }
+
+    #[test]
+    fn test_rejected_order_leaves_client_order_id_free() {
+        let mut engine = engine();
+        let mut order = limit_buy("SOL_USDC");
+        order.client_order_id = Some("quote-1".to_string());
+        order.price = Some("abc".to_string());
+        assert_eq!(engine.create_order(&order, "user1").err(), Some(RejectReason::InvalidPrice));
+
+        order.price = Some("10".to_string());
+        assert!(engine.create_order(&order, "user1").is_ok());
+
+        order.client_order_id = Some(String::new());
+        assert_eq!(engine.create_order(&order, "user1").err(), Some(RejectReason::InvalidClientOrderId));
+    }
+
+    #[test]
+    fn test_continuous_trading_conserves_every_asset() {
+        let mut engine = with_fees(MarketConfig::defaults().remove(0));
+        let (sol, usdc) = (total(&engine, "SOL"), total(&engine, "USDC"));
+
+        engine.create_order(&order(OrderSide::Sell, "10", "3"), "user1").unwrap();
+        engine.create_order(&order(OrderSide::Sell, "10.5", "2"), "user2").unwrap();
+        let sweep = engine.create_order(&order(OrderSide::Buy, "11", "4"), "user3").unwrap();
+        assert_eq!(sweep.status, OrderStatus::Filled);
+
+        let resting = engine.create_order(&order(OrderSide::Buy, "9", "1"), "user1").unwrap();
+        engine.cancel_order(&resting.order_id, "SOL_USDC", "user1").unwrap();
+        engine.create_order(&order(OrderSide::Buy, "9.5", "2"), "user3").unwrap();
+        let mut market_sell = order(OrderSide::Sell, "0", "1");
+        market_sell.order_type = OrderType::Market;
+        market_sell.price = None;
+        let placed = engine.create_order(&market_sell, "user2").unwrap();
+        assert_eq!(placed.executed_qty, Decimal::from_int(1));
+
+        assert_eq!(total(&engine, "SOL"), sol);
+        assert_eq!(total(&engine, "USDC"), usdc);
+        // Fills settle in the market's assets, never in the market symbol
+        assert!(engine.balances.values().all(|balances| !balances.contains_key("SOL_USDC")));
+        assert!(engine.balances[FEE_ACCOUNT]["SOL"].available.is_positive());
+        // Rebates never outrun the fees collected in the same asset
+        assert!(engine.balances[FEE_ACCOUNT].values().all(|b| b.available >= Decimal::ZERO));
+        assert_no_negative_locks(&engine);
+    }
+
+    #[test]
+    fn test_auction_uncross_conserves_every_asset() {
+        let mut config = MarketConfig::defaults().remove(0);
+        config.spec.matching = MatchingMode::BatchAuction { interval_ms: 1_000 };
+        let mut engine = with_fees(config);
+        engine.tick(0);
+        let (sol, usdc) = (total(&engine, "SOL"), total(&engine, "USDC"));
+
+        engine.create_order(&order(OrderSide::Buy, "10.5", "2"), "user1").unwrap();
+        engine.create_order(&order(OrderSide::Sell, "9.9", "1"), "user2").unwrap();
+        engine.create_order(&order(OrderSide::Sell, "10", "3"), "user3").unwrap();
+        engine.tick(1_000);
+        assert_eq!(engine.orderbooks[0].last_price(), Decimal::from_int(10));
+
+        assert_eq!(total(&engine, "SOL"), sol);
+        assert_eq!(total(&engine, "USDC"), usdc);
+        // The buy locked 10.5 a unit but paid the auction price
+        assert_eq!(engine.balances["user1"]["USDC"].locked, Decimal::ZERO);
+        assert_no_negative_locks(&engine);
+    }
+
+    #[test]
+    fn test_limit_orders_lock_funds_before_matching() {
+        let mut engine = engine();
+        let start = Decimal::from_int(10_000_000);
+
+        let bid = engine.create_order(&order(OrderSide::Buy, "10", "10"), "user1").unwrap();
+        assert_eq!(engine.balances["user1"]["USDC"].locked, Decimal::from_int(100));
+        assert_eq!(engine.balances["user1"]["USDC"].available, start - Decimal::from_int(100));
+
+        let too_big = order(OrderSide::Buy, "200", "100000");
+        assert_eq!(engine.create_order(&too_big, "user1").err(), Some(RejectReason::InsufficientFunds));
+        let absurd = order(OrderSide::Buy, "1000000000000000000000", "1000");
+        assert_eq!(engine.create_order(&absurd, "user1").err(), Some(RejectReason::NotionalAboveMaximum));
+        assert_eq!(engine.orderbooks[0].get_depth().bids, vec![("10".to_string(), "10".to_string())]);
+
+        // The seller's base is locked and spent; the bid keeps what backs its rest
+        engine.create_order(&order(OrderSide::Sell, "9", "5"), "user2").unwrap();
+        assert_eq!(engine.balances["user2"]["SOL"].locked, Decimal::ZERO);
+        assert_eq!(engine.balances["user1"]["USDC"].locked, Decimal::from_int(50));
+
+        engine.cancel_order(&bid.order_id, "SOL_USDC", "user1").unwrap();
+        assert_eq!(engine.balances["user1"]["USDC"].locked, Decimal::ZERO);
+        assert_eq!(engine.balances["user1"]["USDC"].available, start - Decimal::from_int(50));
+
+        // A buy that fills below its limit only keeps what it paid
+        engine.create_order(&order(OrderSide::Sell, "9", "2"), "user2").unwrap();
+        engine.create_order(&order(OrderSide::Buy, "11", "2"), "user3").unwrap();
+        assert_eq!(engine.balances["user3"]["USDC"].locked, Decimal::ZERO);
+        assert_eq!(engine.balances["user3"]["USDC"].available, start - Decimal::from_int(18));
+
+        let mut expiring = order(OrderSide::Sell, "12", "3");
+        expiring.expires_at = Some(now_millis() + 60_000);
+        engine.create_order(&expiring, "user3").unwrap();
+        assert_eq!(engine.balances["user3"]["SOL"].locked, Decimal::from_int(3));
+        engine.tick(now_millis() + 120_000);
+        assert_eq!(engine.balances["user3"]["SOL"].locked, Decimal::ZERO);
+        assert_no_negative_locks(&engine);
+    }
+
+    #[test]
+    fn test_markets_and_deposits_need_listed_assets() {
+        let mut engine = engine();
+        let mut eth = MarketConfig::defaults().remove(0);
+        eth.market.symbol = "ETH_USDC".to_string();
+        eth.market.base_asset = "ETH".to_string();
+        assert_eq!(engine.list_market(eth.clone(), 0).err(), Some(RejectReason::UnknownAsset));
+        assert_eq!(engine.on_ramp("user1", "ETH", Decimal::from_int(1)), Err(RejectReason::UnknownAsset));
+
+        engine.list_asset(Asset { symbol: "ETH".to_string(), precision: 8 }).unwrap();
+        assert_eq!(engine.list_asset(Asset { symbol: "ETH".to_string(), precision: 6 }).err(), Some(RejectReason::InvalidAsset));
+        let mut fine_tick = eth.clone();
+        fine_tick.spec.tick_size = "0.0000001".parse().unwrap();
+        assert_eq!(engine.list_market(fine_tick, 0).err(), Some(RejectReason::AssetPrecisionExceeded));
+        engine.list_market(eth, 0).unwrap();
+
+        assert_eq!(engine.on_ramp("user1", "USDC", "0.0000001".parse().unwrap()), Err(RejectReason::InvalidAmount));
+        engine.on_ramp("user1", "ETH", "1.5".parse().unwrap()).unwrap();
+        assert_eq!(engine.balances["user1"]["ETH"].available, "1.5".parse().unwrap());
+    }
+
+    #[test]
+    fn test_snapshot_keeps_market_status() {
+        let mut engine = engine();
+        engine.set_market_status("SOL_USDC", MarketStatus::Halted, 0).unwrap();
+
+        let snapshot = engine.snapshot();
+        assert_eq!(snapshot["markets"]["SOL_USDC"]["status"], "halted");
+        assert_eq!(snapshot["markets"]["SOL_USDC"]["base_asset"], "SOL");
+    }
+
+    #[test]
+    fn test_snapshot_round_trip_keeps_expiries_switches_and_client_orders() {
+        let mut engine = engine();
+        let expires_at = now_millis() + 60_000;
+        let mut good_till = limit_buy("SOL_USDC");
+        good_till.expires_at = Some(expires_at);
+        let expiring = engine.create_order(&good_till, "user1").unwrap();
+        let guarded = engine.create_order(&limit_buy("SOL_USDC"), "user2").unwrap();
+        engine.set_dead_man_switch("user2", 5_000, expires_at);
+        let mut quote = limit_buy("SOL_USDC");
+        quote.client_order_id = Some("quote-1".to_string());
+        let quoted = engine.create_order(&quote, "user3").unwrap();
+
+        let mut restored = Engine::restore(engine.snapshot()).unwrap();
+        assert_eq!(restored.expiries, engine.expiries);
+        assert_eq!(restored.dead_man_switches, engine.dead_man_switches);
+        // A retry after a restart still finds the order it placed
+        assert_eq!(restored.create_order(&quote, "user3").unwrap().order_id, quoted.order_id);
+        assert!(restored.orderbooks[0].get_order(&expiring.order_id).is_some());
+
+        restored.tick(expires_at);
+        assert!(restored.orderbooks[0].get_order(&expiring.order_id).is_none());
+        assert!(restored.orderbooks[0].get_order(&guarded.order_id).is_some());
+        restored.tick(expires_at + 5_000);
+        assert!(restored.orderbooks[0].get_order(&guarded.order_id).is_none());
+        assert_eq!(restored.balances["user1"]["USDC"].locked, Decimal::ZERO);
+    }
+}
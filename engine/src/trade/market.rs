@@ -0,0 +1,480 @@
+use std::collections::VecDeque;
+use std::fs;
+use serde::{Deserialize, Serialize};
+use crate::trade::decimal::Decimal;
+use crate::trade::orderbook::{Order, OrderType};
+use crate::types::api::RejectReason;
+
+/// Trading rules of one market. Every order is checked against them before
+/// it reaches the book.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarketSpec {
+    /// Prices must be a multiple of this
+    pub tick_size: Decimal,
+    /// Quantities must be a multiple of this
+    pub step_size: Decimal,
+    pub min_quantity: Decimal,
+    pub max_quantity: Option<Decimal>,
+    /// Smallest price * quantity an order may have
+    pub min_notional: Decimal,
+    /// Most fractional digits a price may have
+    pub price_precision: u32,
+    /// How far, in percent, an order's price may be from the reference price
+    pub price_band_percent: Option<Decimal>,
+    /// Reference price for the bands until the market has traded
+    pub reference_price: Option<Decimal>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Length of the opening auction held when the market is listed or
+    /// resumes from a halt; no auction if unset
+    pub auction_secs: Option<u64>,
+    pub matching: MatchingMode,
+    /// How fills are shared among the orders of one price level
+    pub allocation: Allocation,
+    /// Fraction of what a resting order receives that it pays as a fee; a
+    /// negative rate is a rebate
+    pub maker_fee_rate: Decimal,
+    /// Fraction of what an incoming order receives that it pays as a fee
+    pub taker_fee_rate: Decimal,
+}
+
+/// How a market's book turns orders into trades.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MatchingMode {
+    /// Orders match as they arrive
+    #[default]
+    Continuous,
+    /// Orders rest without matching and the book is cleared at a single
+    /// price every `interval_ms`
+    BatchAuction { interval_ms: u64 },
+}
+
+/// How an incoming order's quantity is shared among the orders resting at
+/// one price level during continuous matching.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Allocation {
+    /// Oldest order first
+    #[default]
+    PriceTime,
+    /// In proportion to each order's displayed size, rounded down to the
+    /// step size; what rounding leaves over goes out in time priority
+    ProRata {
+        /// Fill the oldest order at the level before sharing out the rest
+        #[serde(default)]
+        top_priority: bool,
+        /// Shares smaller than this are dropped and go to the time-priority
+        /// remainder instead
+        #[serde(default)]
+        min_allocation: Decimal,
+    },
+}
+
+/// Halts a market for `halt_secs` when its trade price moves more than
+/// `move_percent` within `window_secs`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CircuitBreaker {
+    pub move_percent: Decimal,
+    pub window_secs: u64,
+    pub halt_secs: u64,
+}
+
+impl Default for MarketSpec {
+    /// No restrictions beyond what `Decimal` can represent.
+    fn default() -> Self {
+        Self {
+            tick_size: Decimal::EPSILON,
+            step_size: Decimal::EPSILON,
+            min_quantity: Decimal::EPSILON,
+            max_quantity: None,
+            min_notional: Decimal::ZERO,
+            price_precision: Decimal::DECIMALS,
+            price_band_percent: None,
+            reference_price: None,
+            circuit_breaker: None,
+            auction_secs: None,
+            matching: MatchingMode::Continuous,
+            allocation: Allocation::PriceTime,
+            maker_fee_rate: Decimal::ZERO,
+            taker_fee_rate: Decimal::ZERO,
+        }
+    }
+}
+
+impl MarketSpec {
+    /// Checks a new or amended order. The notional is taken at the limit
+    /// price, or at the stop price for stop-market orders; market orders have
+    /// no price of their own and only get their quantity checked.
+    pub fn validate(&self, order: &Order) -> Result<(), RejectReason> {
+        self.validate_quantity(order.quantity)?;
+        if let Some(iceberg) = &order.iceberg {
+            if !iceberg.display_quantity.is_multiple_of(self.step_size) {
+                return Err(RejectReason::InvalidDisplayQuantity);
+            }
+        }
+
+        if let Some(stop_price) = order.stop_price {
+            if !stop_price.is_positive() {
+                return Err(RejectReason::InvalidStopPrice);
+            }
+            self.validate_price(stop_price)?;
+        }
+
+        let notional_price = match order.order_type {
+            OrderType::Limit | OrderType::StopLimit => {
+                if !order.price.is_positive() {
+                    return Err(RejectReason::InvalidPrice);
+                }
+                self.validate_price(order.price)?;
+                Some(order.price)
+            }
+            OrderType::Market | OrderType::StopMarket => order.stop_price,
+        };
+        if let Some(price) = notional_price {
+            // Prices have no upper bound, so the notional may not fit at all
+            let notional = price.checked_mul(order.quantity).ok_or(RejectReason::NotionalAboveMaximum)?;
+            if notional < self.min_notional {
+                return Err(RejectReason::NotionalBelowMinimum);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lowest and highest price orders may have while the reference price is
+    /// `last_price` (zero before the first trade). `None` if the market has
+    /// no bands or nothing to anchor them to yet.
+    pub fn price_band(&self, last_price: Decimal) -> Option<(Decimal, Decimal)> {
+        let percent = self.price_band_percent?;
+        let reference = Some(last_price)
+            .filter(Decimal::is_positive)
+            .or(self.reference_price)?;
+        let offset = reference * percent / Decimal::from_int(100);
+        Some((reference - offset, reference + offset))
+    }
+
+    /// Checks a limit price against the bands around `last_price`.
+    pub fn check_price_band(&self, order: &Order, last_price: Decimal) -> Result<(), RejectReason> {
+        let priced = matches!(order.order_type, OrderType::Limit | OrderType::StopLimit);
+        match self.price_band(last_price) {
+            Some((low, high)) if priced && (order.price < low || order.price > high) => Err(RejectReason::PriceOutsideBand),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_quantity(&self, quantity: Decimal) -> Result<(), RejectReason> {
+        if !quantity.is_positive() {
+            return Err(RejectReason::InvalidQuantity);
+        }
+        if !quantity.is_multiple_of(self.step_size) {
+            return Err(RejectReason::InvalidStepSize);
+        }
+        if quantity < self.min_quantity {
+            return Err(RejectReason::QuantityBelowMinimum);
+        }
+        if self.max_quantity.is_some_and(|max| quantity > max) {
+            return Err(RejectReason::QuantityAboveMaximum);
+        }
+        Ok(())
+    }
+
+    fn validate_price(&self, price: Decimal) -> Result<(), RejectReason> {
+        if price.scale() > self.price_precision {
+            return Err(RejectReason::PricePrecisionExceeded);
+        }
+        if !price.is_multiple_of(self.tick_size) {
+            return Err(RejectReason::InvalidTickSize);
+        }
+        Ok(())
+    }
+}
+
+/// Recent trade prices of a market, kept for its circuit breaker.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PriceWindow {
+    trades: VecDeque<(i64, Decimal)>,
+}
+
+impl PriceWindow {
+    /// Records a trade at `now` (milliseconds) and returns whether its price
+    /// is further than the breaker allows from the oldest trade still inside
+    /// the window.
+    pub fn record(&mut self, breaker: &CircuitBreaker, now: i64, price: Decimal) -> bool {
+        let window_start = now - breaker.window_secs as i64 * 1000;
+        while self.trades.front().is_some_and(|&(at, _)| at < window_start) {
+            self.trades.pop_front();
+        }
+        self.trades.push_back((now, price));
+
+        let (_, reference) = self.trades[0];
+        (price - reference).abs() * Decimal::from_int(100) > reference * breaker.move_percent
+    }
+
+    pub fn clear(&mut self) {
+        self.trades.clear();
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketStatus {
+    #[default]
+    Trading,
+    /// Orders are collected without matching until the auction uncrosses
+    Auction,
+    /// Resting orders can be cancelled, but nothing new is accepted
+    CancelOnly,
+    /// The book is frozen: no new orders, amends or cancels
+    Halted,
+    /// Closed for good; resting orders can still be cancelled
+    Delisted,
+}
+
+impl MarketStatus {
+    /// Whether new orders and amends are accepted.
+    pub fn accepts_orders(self) -> bool {
+        matches!(self, MarketStatus::Trading | MarketStatus::Auction)
+    }
+
+    pub fn accepts_cancels(self) -> bool {
+        self != MarketStatus::Halted
+    }
+}
+
+/// Registry entry for a listed market. Its trading rules live with its
+/// `Orderbook`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Market {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    #[serde(default)]
+    pub status: MarketStatus,
+}
+
+/// Everything needed to list a market, as read from the markets file or sent
+/// by an admin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketConfig {
+    #[serde(flatten)]
+    pub market: Market,
+    #[serde(default)]
+    pub spec: MarketSpec,
+}
+
+impl MarketConfig {
+    /// Markets listed when no markets file is present.
+    pub fn defaults() -> Vec<MarketConfig> {
+        vec![MarketConfig {
+            market: Market {
+                symbol: "SOL_USDC".to_string(),
+                base_asset: "SOL".to_string(),
+                quote_asset: "USDC".to_string(),
+                status: MarketStatus::Trading,
+            },
+            spec: MarketSpec {
+                tick_size: "0.01".parse().unwrap(),
+                step_size: "0.01".parse().unwrap(),
+                min_quantity: "0.01".parse().unwrap(),
+                max_quantity: Some(Decimal::from_int(100_000)),
+                min_notional: Decimal::from_int(1),
+                price_precision: 2,
+                ..MarketSpec::default()
+            },
+        }]
+    }
+
+    /// Reads a JSON array of market configs.
+    pub fn load(path: &str) -> Result<Vec<MarketConfig>, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let configs: Vec<MarketConfig> = serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+        for config in &configs {
+            config.validate().map_err(|reason| format!("{}: {}: {}", path, config.market.symbol, reason))?;
+        }
+        Ok(configs)
+    }
+
+    pub fn validate(&self) -> Result<(), RejectReason> {
+        let Market { symbol, base_asset, quote_asset, .. } = &self.market;
+        if symbol.is_empty() || base_asset.is_empty() || quote_asset.is_empty() || base_asset == quote_asset {
+            return Err(RejectReason::InvalidMarket);
+        }
+        if !self.spec.tick_size.is_positive() || !self.spec.step_size.is_positive() {
+            return Err(RejectReason::InvalidMarket);
+        }
+        if self.spec.max_quantity.is_some_and(|max| max < self.spec.min_quantity) {
+            return Err(RejectReason::InvalidMarket);
+        }
+        if self.spec.price_band_percent.is_some_and(|percent| !percent.is_positive()) {
+            return Err(RejectReason::InvalidMarket);
+        }
+        if self.spec.circuit_breaker.as_ref().is_some_and(|b| !b.move_percent.is_positive() || b.window_secs == 0) {
+            return Err(RejectReason::InvalidMarket);
+        }
+        if self.spec.matching == (MatchingMode::BatchAuction { interval_ms: 0 }) {
+            return Err(RejectReason::InvalidMarket);
+        }
+        // A maker rebate is paid out of the taker's fee on the same fill
+        if self.spec.taker_fee_rate < Decimal::ZERO
+            || self.spec.taker_fee_rate >= Decimal::from_int(1)
+            || self.spec.maker_fee_rate >= Decimal::from_int(1)
+            || self.spec.maker_fee_rate + self.spec.taker_fee_rate < Decimal::ZERO
+        {
+            return Err(RejectReason::InvalidMarket);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::redis_manager::OrderSide;
+    use crate::trade::orderbook::{SelfTradePrevention, TimeInForce};
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn spec() -> MarketSpec {
+        MarketSpec {
+            tick_size: dec("0.05"),
+            step_size: dec("0.1"),
+            min_quantity: dec("0.5"),
+            max_quantity: Some(dec("1000")),
+            min_notional: dec("10"),
+            price_precision: 2,
+            ..MarketSpec::default()
+        }
+    }
+
+    fn order(price: &str, quantity: &str) -> Order {
+        Order {
+            price: dec(price),
+            quantity: dec(quantity),
+            order_id: "order".to_string(),
+            filled: Decimal::ZERO,
+            side: OrderSide::Buy,
+            user_id: "user1".to_string(),
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            post_only: None,
+            stop_price: None,
+            iceberg: None,
+            self_trade_prevention: SelfTradePrevention::CancelNewest,
+            expires_at: None,
+            client_order_id: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_accepts_order_within_rules() {
+        assert_eq!(spec().validate(&order("20.05", "0.5")), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_rejects_each_rule() {
+        let spec = spec();
+        assert_eq!(spec.validate(&order("0", "1")), Err(RejectReason::InvalidPrice));
+        assert_eq!(spec.validate(&order("20", "0")), Err(RejectReason::InvalidQuantity));
+        assert_eq!(spec.validate(&order("20", "-1")), Err(RejectReason::InvalidQuantity));
+        assert_eq!(spec.validate(&order("20", "1.25")), Err(RejectReason::InvalidStepSize));
+        assert_eq!(spec.validate(&order("100", "0.4")), Err(RejectReason::QuantityBelowMinimum));
+        assert_eq!(spec.validate(&order("20", "1000.1")), Err(RejectReason::QuantityAboveMaximum));
+        assert_eq!(spec.validate(&order("20.001", "1")), Err(RejectReason::PricePrecisionExceeded));
+        assert_eq!(spec.validate(&order("20.02", "1")), Err(RejectReason::InvalidTickSize));
+        assert_eq!(spec.validate(&order("19.95", "0.5")), Err(RejectReason::NotionalBelowMinimum));
+        assert_eq!(spec.validate(&order("1000000000000000000000", "1000")), Err(RejectReason::NotionalAboveMaximum));
+    }
+
+    #[test]
+    fn test_validate_market_and_stop_orders() {
+        let spec = spec();
+        let mut market = order("0", "0.5");
+        market.order_type = OrderType::Market;
+        assert_eq!(spec.validate(&market), Ok(()));
+
+        let mut stop = order("0", "0.5");
+        stop.order_type = OrderType::StopMarket;
+        stop.stop_price = Some(dec("19.95"));
+        assert_eq!(spec.validate(&stop), Err(RejectReason::NotionalBelowMinimum));
+        stop.stop_price = Some(dec("30.01"));
+        assert_eq!(spec.validate(&stop), Err(RejectReason::InvalidTickSize));
+    }
+
+    #[test]
+    fn test_price_band_around_last_or_reference_price() {
+        let mut spec = spec();
+        assert_eq!(spec.price_band(dec("20")), None);
+
+        spec.price_band_percent = Some(dec("10"));
+        assert_eq!(spec.price_band(Decimal::ZERO), None);
+        assert_eq!(spec.price_band(dec("20")), Some((dec("18"), dec("22"))));
+        spec.reference_price = Some(dec("100"));
+        assert_eq!(spec.price_band(Decimal::ZERO), Some((dec("90"), dec("110"))));
+
+        assert_eq!(spec.check_price_band(&order("22", "1"), dec("20")), Ok(()));
+        assert_eq!(spec.check_price_band(&order("22.05", "1"), dec("20")), Err(RejectReason::PriceOutsideBand));
+        assert_eq!(spec.check_price_band(&order("17.95", "1"), dec("20")), Err(RejectReason::PriceOutsideBand));
+        let mut market = order("0", "1");
+        market.order_type = OrderType::Market;
+        assert_eq!(spec.check_price_band(&market, dec("20")), Ok(()));
+    }
+
+    #[test]
+    fn test_price_window_trips_on_moves_within_window() {
+        let breaker = CircuitBreaker { move_percent: dec("5"), window_secs: 60, halt_secs: 300 };
+        let mut window = PriceWindow::default();
+        assert!(!window.record(&breaker, 0, dec("100")));
+        assert!(!window.record(&breaker, 10_000, dec("104")));
+        assert!(window.record(&breaker, 20_000, dec("94.9")));
+
+        // The 100 and 104 trades have left the window by now
+        let mut window = PriceWindow::default();
+        window.record(&breaker, 0, dec("100"));
+        assert!(!window.record(&breaker, 61_000, dec("110")));
+        assert!(!window.record(&breaker, 62_000, dec("115")));
+    }
+
+    #[test]
+    fn test_market_status_permissions() {
+        assert!(MarketStatus::Trading.accepts_orders() && MarketStatus::Trading.accepts_cancels());
+        assert!(MarketStatus::Auction.accepts_orders() && MarketStatus::Auction.accepts_cancels());
+        assert!(!MarketStatus::CancelOnly.accepts_orders() && MarketStatus::CancelOnly.accepts_cancels());
+        assert!(!MarketStatus::Halted.accepts_orders() && !MarketStatus::Halted.accepts_cancels());
+        assert!(!MarketStatus::Delisted.accepts_orders() && MarketStatus::Delisted.accepts_cancels());
+        assert_eq!(serde_json::to_string(&MarketStatus::CancelOnly).unwrap(), r#""cancel_only""#);
+    }
+
+    #[test]
+    fn test_market_config_defaults_and_validation() {
+        let config: MarketConfig = serde_json::from_str(
+            r#"{"symbol": "ETH_USDC", "base_asset": "ETH", "quote_asset": "USDC", "spec": {"tick_size": "0.1"}}"#
+        ).unwrap();
+        assert_eq!(config.market.status, MarketStatus::Trading);
+        assert_eq!(config.spec.tick_size, dec("0.1"));
+        assert_eq!(config.spec.step_size, Decimal::EPSILON);
+        assert_eq!(config.validate(), Ok(()));
+
+        let mut same_assets = config.clone();
+        same_assets.market.quote_asset = "ETH".to_string();
+        assert_eq!(same_assets.validate(), Err(RejectReason::InvalidMarket));
+
+        let mut no_tick = config.clone();
+        no_tick.spec.tick_size = Decimal::ZERO;
+        assert_eq!(no_tick.validate(), Err(RejectReason::InvalidMarket));
+
+        let batch: MatchingMode = serde_json::from_str(r#"{"type": "batch_auction", "interval_ms": 500}"#).unwrap();
+        assert_eq!(batch, MatchingMode::BatchAuction { interval_ms: 500 });
+        let mut no_interval = config.clone();
+        no_interval.spec.matching = MatchingMode::BatchAuction { interval_ms: 0 };
+        assert_eq!(no_interval.validate(), Err(RejectReason::InvalidMarket));
+
+        let mut rebate = config.clone();
+        rebate.spec.taker_fee_rate = dec("0.001");
+        rebate.spec.maker_fee_rate = dec("-0.001");
+        assert_eq!(rebate.validate(), Ok(()));
+        rebate.spec.maker_fee_rate = dec("-0.002");
+        assert_eq!(rebate.validate(), Err(RejectReason::InvalidMarket));
+    }
+}
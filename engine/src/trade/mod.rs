@@ -1,2 +1,7 @@
+pub mod asset;
+pub mod auction;
+pub mod decimal;
 pub mod engine;
-pub mod orderbook;
\ No newline at end of file
+pub mod market;
+pub mod orderbook;
+pub mod trigger;
\ No newline at end of file
@@ -1,53 +1,250 @@
 use serde::{Deserialize, Serialize};
 use crate::redis::redis_manager::OrderSide;
+use crate::trade::decimal::Decimal;
+use crate::trade::market::{Allocation, MarketSpec, MatchingMode};
+use crate::types::api::RejectReason;
 use log::info;
+use slab::Slab;
 use std::collections::{BTreeMap, HashMap};
-use std::cmp::Ordering;
-
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-pub struct Price(f64);
-
-impl Eq for Price {}
-
-impl PartialOrd for Price {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.0.partial_cmp(&other.0)
-    }
-}
-
-impl Ord for Price {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
-    }
-}
+use std::ops::Bound::{Excluded, Unbounded};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Fill {
-    pub qty: f64,
-    pub price: f64,
+    pub qty: Decimal,
+    pub price: Decimal,
     pub trade_id: i64,
     pub marker_order_id: String,
     pub other_user_id: String,
+    /// Fee the incoming order paid on what it received, set at settlement
+    #[serde(default)]
+    pub fee: Decimal,
+    #[serde(default)]
+    pub fee_asset: String,
+    /// Fee the resting order paid on what it received, or a rebate (negative)
+    /// paid in `fee_asset`
+    #[serde(default)]
+    pub maker_fee: Decimal,
+    #[serde(default)]
+    pub maker_fee_asset: String,
+}
+
+/// Aggregated (price, quantity) of one price level.
+pub type Level = (Decimal, Decimal);
+
+/// Outcome of uncrossing the book at the end of an auction.
+#[derive(Debug, Default)]
+pub struct Uncross {
+    /// Each buy order that traded, as it stands afterwards, with its fills
+    /// against the sell orders
+    pub trades: Vec<(Order, Vec<Fill>)>,
+    /// Each sell order that traded, as it stands afterwards
+    pub sells: Vec<Order>,
+    /// Orders reduced because they would have traded with their own user,
+    /// with the quantity taken off
+    pub cancelled: Vec<(Order, Decimal)>,
+}
+
+/// Outcome of running an incoming order against the book.
+#[derive(Debug, Default)]
+pub struct Execution {
+    pub fills: Vec<Fill>,
+    pub executed_qty: Decimal,
+    /// Part of the incoming order cancelled by self-trade prevention
+    pub self_trade_cancelled_qty: Decimal,
+    /// Resting orders of the same user reduced or cancelled by self-trade
+    /// prevention, as left afterwards, with the quantity taken off each
+    pub cancelled_makers: Vec<(Order, Decimal)>,
+    /// Resting orders that traded, each once, as left afterwards
+    pub makers: Vec<Order>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Orderbook {
     market: String,
-    pub bids: BTreeMap<Price, Vec<Order>>,
-    pub asks: BTreeMap<Price, Vec<Order>>,
+    bids: BTreeMap<Decimal, PriceLevel>,
+    asks: BTreeMap<Decimal, PriceLevel>,
     last_trade_id: i64,
-    current_price: f64,
-    orders: HashMap<String, Order>,
+    current_price: Decimal,
+    /// Every resting order, linked into the queue of its price level
+    orders: Slab<RestingOrder>,
+    /// Order id -> key of the resting order in `orders`
+    order_index: HashMap<String, usize>,
+    spec: MarketSpec,
+}
+
+/// FIFO queue of the orders resting at one price. The orders live in the
+/// book's slab and link to their neighbours, so any of them can leave the
+/// queue in O(1).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct PriceLevel {
+    head: Option<usize>,
+    tail: Option<usize>,
+    len: usize,
+}
+
+/// A resting order and its neighbours in the queue of its price level.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RestingOrder {
+    order: Order,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,
+    /// Becomes a market order once the last trade reaches `stop_price`
+    StopMarket,
+    /// Becomes a limit order once the last trade reaches `stop_price`
+    StopLimit,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    /// Good till cancelled: the unfilled remainder rests on the book
+    #[default]
+    Gtc,
+    /// Immediate or cancel: fill what is available now, cancel the rest
+    Ioc,
+    /// Fill or kill: fill the whole quantity immediately or nothing at all
+    Fok,
+}
+
+/// What to do with a post-only order that would take liquidity.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostOnly {
+    /// Reject the order outright
+    Reject,
+    /// Move the price one tick behind the opposite best so it rests as a maker
+    Reprice,
+}
+
+/// What happens when an incoming order would trade against a resting order
+/// of the same user. Trading with yourself is never allowed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTradePrevention {
+    /// Cancel the rest of the incoming order
+    #[default]
+    CancelNewest,
+    /// Cancel the resting order and keep matching
+    CancelOldest,
+    /// Cancel both the resting order and the rest of the incoming order
+    CancelBoth,
+    /// Take the smaller remaining quantity off both orders, cancelling
+    /// whichever reaches zero, and keep matching
+    DecrementAndCancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Expired,
+    Rejected,
+}
+
+impl OrderStatus {
+    /// Whether an order in this status can still trade or be cancelled.
+    pub fn is_open(self) -> bool {
+        matches!(self, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
-    pub price: f64,
-    pub quantity: f64,
+    pub price: Decimal,
+    pub quantity: Decimal,
     pub order_id: String,
-    pub filled: f64,
+    pub filled: Decimal,
     pub side: OrderSide,
     pub user_id: String,
+    #[serde(default)]
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub post_only: Option<PostOnly>,
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+    #[serde(default)]
+    pub iceberg: Option<Iceberg>,
+    #[serde(default)]
+    pub self_trade_prevention: SelfTradePrevention,
+    /// Time (ms) after which a resting good-till-date order is cancelled
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Id the client chose for the order, unique among the user's orders
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+}
+
+/// Display settings of an iceberg order. Only `displayed` of the order is
+/// visible on the book; the hidden reserve refills it once it is used up.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Iceberg {
+    /// Size of each visible tranche
+    pub display_quantity: Decimal,
+    /// What is left of the current visible tranche
+    pub displayed: Decimal,
+}
+
+impl Order {
+    /// Whether an unfilled remainder of this order may rest on the book.
+    pub fn can_rest(&self) -> bool {
+        self.order_type == OrderType::Limit && self.time_in_force == TimeInForce::Gtc
+    }
+
+    /// Status of this order while it is open: new until it trades, then
+    /// partially filled until nothing is left.
+    pub fn fill_status(&self) -> OrderStatus {
+        if self.filled >= self.quantity {
+            OrderStatus::Filled
+        } else if self.filled.is_positive() {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::New
+        }
+    }
+
+    /// Quantity shown on the book: the current tranche for icebergs,
+    /// otherwise everything still unfilled.
+    pub fn displayed_quantity(&self) -> Decimal {
+        let remaining = self.quantity - self.filled;
+        match self.iceberg {
+            Some(iceberg) => iceberg.displayed.min(remaining),
+            None => remaining,
+        }
+    }
+
+    /// Records a fill against this order while it rests on the book.
+    fn fill_resting(&mut self, qty: Decimal) {
+        self.filled += qty;
+        if let Some(iceberg) = &mut self.iceberg {
+            iceberg.displayed -= qty;
+        }
+    }
+
+    /// Loads the next tranche of an iceberg from its hidden reserve once the
+    /// visible one is used up. Returns whether a new tranche was loaded.
+    fn refresh_tranche(&mut self) -> bool {
+        let remaining = self.quantity - self.filled;
+        match &mut self.iceberg {
+            Some(iceberg) if !iceberg.displayed.is_positive() && remaining.is_positive() => {
+                iceberg.displayed = iceberg.display_quantity.min(remaining);
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,14 +255,20 @@ pub struct OrderbookSnapshot {
 
 impl Orderbook {
     pub fn new(market: String) -> Self {
+        Self::with_spec(market, MarketSpec::default())
+    }
+
+    pub fn with_spec(market: String, spec: MarketSpec) -> Self {
         info!("Creating new orderbook for market: {}", market);
         Self {
             market,
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
-            orders: HashMap::new(),
+            orders: Slab::new(),
+            order_index: HashMap::new(),
             last_trade_id: 0,
-            current_price: 0.0,
+            current_price: Decimal::ZERO,
+            spec,
         }
     }
 
@@ -73,6 +276,16 @@ impl Orderbook {
         &self.market  // Return reference to market string
     }
 
+    pub fn spec(&self) -> &MarketSpec {
+        &self.spec
+    }
+
+    /// New rules only apply to orders placed from now on; resting orders
+    /// are left as they are.
+    pub fn set_spec(&mut self, spec: MarketSpec) {
+        self.spec = spec;
+    }
+
     #[allow(dead_code)]
     pub fn get_snapshot(&self) -> Orderbook {
         let snapshot = Orderbook {
@@ -80,36 +293,162 @@ impl Orderbook {
             bids: self.bids.clone(),
             asks: self.asks.clone(),
             orders: self.orders.clone(),
+            order_index: self.order_index.clone(),
             last_trade_id: self.last_trade_id,
             current_price: self.current_price,
+            spec: self.spec.clone(),
         };
 
         snapshot
     }
 
-    pub fn add_order(&mut self, order: &mut Order) -> Result<(Vec<Fill>, f64), String> {
-        if order.side == OrderSide::Buy {
-            let (fills, executed_qty) = self.match_bid(order).expect("Error matching bid");
-            order.filled = executed_qty;
-            if executed_qty == order.quantity {
-                Ok((fills, executed_qty))
-            } else {
-                self.bids.entry(Price(order.price))
-                    .and_modify(|bids| bids.push(order.clone()))
-                    .or_insert_with(|| vec![order.clone()]);
-                Ok((fills, executed_qty))
+    pub fn add_order(&mut self, order: &mut Order) -> Result<Execution, RejectReason> {
+        // A batch auction book only collects orders; they trade when the
+        // engine clears the batch
+        if let MatchingMode::BatchAuction { .. } = self.spec.matching {
+            if !order.can_rest() {
+                return Err(RejectReason::NotAllowedInAuction);
             }
-        } else {
-            let (fills, executed_qty) = self.match_ask(order).expect("Error matching ask");
-            order.filled = executed_qty;
-            if executed_qty == order.quantity {
-                Ok((fills, executed_qty))
+            self.rest_order(order);
+            return Ok(Execution::default());
+        }
+
+        if let Some(mode) = order.post_only {
+            self.apply_post_only(order, mode)?;
+        }
+
+        // Fill-or-kill is all or nothing, so check the book before any fill is applied
+        if order.time_in_force == TimeInForce::Fok && self.estimate_fill(order).0 < order.quantity {
+            return Ok(Execution::default());
+        }
+
+        let execution = self.match_order(order);
+        order.filled += execution.executed_qty;
+        // Whatever self-trade prevention cancelled is gone from the order for good
+        order.quantity -= execution.self_trade_cancelled_qty;
+        if let Some(last_fill) = execution.fills.last() {
+            self.current_price = last_fill.price;
+        }
+
+        if order.filled < order.quantity && order.can_rest() {
+            self.rest_order(order);
+        }
+        Ok(execution)
+    }
+
+    /// Puts `order` on the book without matching it, as during an auction.
+    pub fn rest_order(&mut self, order: &mut Order) {
+        // An iceberg only ever shows its first tranche when it starts resting
+        if let Some(iceberg) = &mut order.iceberg {
+            iceberg.displayed = iceberg.display_quantity.min(order.quantity - order.filled);
+        }
+        self.push_order(order.clone());
+    }
+
+    /// Total unfilled quantity per price level, hidden iceberg reserves
+    /// included, as (bids, asks) with the best price first.
+    pub fn level_totals(&self) -> (Vec<Level>, Vec<Level>) {
+        let total = |(price, level): (&Decimal, &PriceLevel)| {
+            (*price, self.queue(*level).map(|o| o.quantity - o.filled).sum())
+        };
+        (self.bids.iter().rev().map(total).collect(), self.asks.iter().map(total).collect())
+    }
+
+    /// Executes every crossing bid and ask at the single auction `price`,
+    /// pairing them off in price-time priority. When both orders of a pair
+    /// belong to the same user, the overlap is cancelled from both instead
+    /// of trading.
+    pub fn uncross(&mut self, price: Decimal) -> Uncross {
+        let mut uncross = Uncross::default();
+
+        loop {
+            let bid = self.bids.range(price..).next_back().and_then(|(_, level)| level.head);
+            let ask = self.asks.range(..=price).next().and_then(|(_, level)| level.head);
+            let (Some(bid), Some(ask)) = (bid, ask) else {
+                break;
+            };
+
+            let (buy, sell) = (&self.orders[bid].order, &self.orders[ask].order);
+            let qty = (buy.quantity - buy.filled).min(sell.quantity - sell.filled);
+            if buy.user_id == sell.user_id {
+                for key in [bid, ask] {
+                    let order = &mut self.orders[key].order;
+                    order.quantity -= qty;
+                    uncross.cancelled.push((order.clone(), qty));
+                }
             } else {
-                self.asks.entry(Price(order.price))
-                    .and_modify(|asks| asks.push(order.clone()))
-                    .or_insert_with(|| vec![order.clone()]);
-                Ok((fills, executed_qty))
+                self.last_trade_id += 1;
+                let fill = Fill {
+                    price,
+                    qty,
+                    trade_id: self.last_trade_id,
+                    other_user_id: sell.user_id.clone(),
+                    marker_order_id: sell.order_id.clone(),
+                    fee: Decimal::ZERO,
+                    fee_asset: String::new(),
+                    maker_fee: Decimal::ZERO,
+                    maker_fee_asset: String::new(),
+                };
+                for key in [bid, ask] {
+                    let order = &mut self.orders[key].order;
+                    order.filled += qty;
+                    // Auction fills do not cost an iceberg its place
+                    if let Some(iceberg) = &mut order.iceberg {
+                        iceberg.displayed = iceberg.display_quantity.min(order.quantity - order.filled);
+                    }
+                }
+                let sell = self.orders[ask].order.clone();
+                match uncross.sells.iter_mut().find(|order| order.order_id == sell.order_id) {
+                    Some(order) => *order = sell,
+                    None => uncross.sells.push(sell),
+                }
+                let buy = self.orders[bid].order.clone();
+                match uncross.trades.last_mut() {
+                    Some((order, fills)) if order.order_id == buy.order_id => {
+                        *order = buy;
+                        fills.push(fill);
+                    }
+                    _ => uncross.trades.push((buy, vec![fill])),
+                }
+                self.current_price = price;
             }
+
+            for key in [bid, ask] {
+                let order = &self.orders[key].order;
+                if order.filled >= order.quantity {
+                    self.unlink(key);
+                }
+            }
+        }
+        uncross
+    }
+
+    /// Makes sure a post-only order cannot take liquidity, either by rejecting
+    /// it or by repricing it one tick behind the opposite side's best price.
+    pub fn apply_post_only(&self, order: &mut Order, mode: PostOnly) -> Result<(), RejectReason> {
+        // A market order always takes liquidity
+        if order.order_type == OrderType::Market {
+            return Err(RejectReason::PostOnlyWouldCross);
+        }
+
+        let repriced = match order.side {
+            OrderSide::Buy => match self.asks.keys().next() {
+                Some(&best_ask) if order.price >= best_ask => best_ask - self.spec.tick_size,
+                _ => return Ok(()),
+            },
+            OrderSide::Sell => match self.bids.keys().next_back() {
+                Some(&best_bid) if order.price <= best_bid => best_bid + self.spec.tick_size,
+                _ => return Ok(()),
+            },
+        };
+
+        match mode {
+            PostOnly::Reprice if repriced.is_positive() => {
+                info!("Repricing post-only order {} from {} to {}", order.order_id, order.price, repriced);
+                order.price = repriced;
+                Ok(())
+            }
+            _ => Err(RejectReason::PostOnlyWouldCross),
         }
     }
 
@@ -117,189 +456,420 @@ impl Orderbook {
         let mut bids: Vec<(String, String)> = Vec::new();
         let mut asks: Vec<(String, String)> = Vec::new();
         info!("Getting depth for market: {:?}", self.market);
-        // Aggregate bids at same price level
-        for (price, orders) in &self.bids {
-            let remaining = orders.iter().map(|o| o.quantity - o.filled).sum::<f64>();
-            if remaining > 0.0 {
-                bids.push((price.0.to_string(), remaining.to_string()));
+        // Aggregate bids at same price level, highest price first. Only the
+        // displayed part of iceberg orders is shown.
+        for (price, level) in self.bids.iter().rev() {
+            let remaining: Decimal = self.queue(*level).map(|o| o.displayed_quantity()).sum();
+            if remaining.is_positive() {
+                bids.push((price.to_string(), remaining.to_string()));
             }
         }
         info!("Bids: {:?}", bids);
-        // Aggregate asks at same price level
-        for (price, orders) in &self.asks {
-            let remaining = orders.iter().map(|o| o.quantity - o.filled).sum::<f64>();
-            if remaining > 0.0 {
-                asks.push((price.0.to_string(), remaining.to_string()));
+        // Aggregate asks at same price level, lowest price first
+        for (price, level) in &self.asks {
+            let remaining: Decimal = self.queue(*level).map(|o| o.displayed_quantity()).sum();
+            if remaining.is_positive() {
+                asks.push((price.to_string(), remaining.to_string()));
             }
         }
         info!("Asks: {:?}", asks);
-        // Sort bids in descending order (highest price first)
-        bids.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
-        // Sort asks in ascending order (lowest price first)
-        asks.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-        info!("Sorted bids: {:?}", bids);
-        info!("Sorted asks: {:?}", asks); 
         OrderbookSnapshot { bids, asks }
     }
 
+    pub fn get_order(&self, order_id: &str) -> Option<&Order> {
+        self.order_index.get(order_id).map(|&key| &self.orders[key].order)
+    }
+
+    /// Lowers the total quantity of a resting order in place, keeping its
+    /// position in the queue. `quantity` must stay above what is filled.
+    pub fn reduce_order(&mut self, order_id: &str, quantity: Decimal) -> Option<&Order> {
+        let key = *self.order_index.get(order_id)?;
+        let order = &mut self.orders[key].order;
+        order.quantity = order.quantity.min(quantity);
+        Some(order)
+    }
+
+    /// Takes a resting order off the book, dropping its price level if it
+    /// was the last order there.
+    pub fn remove_order(&mut self, order_id: &str) -> Option<Order> {
+        let key = *self.order_index.get(order_id)?;
+        Some(self.unlink(key))
+    }
+
     pub fn get_open_orders(&self, user_id: &str) -> Vec<Order> {
-        let mut orders = Vec::new();
-        orders.extend(
-            self.bids.values()
-                .flat_map(|bids| bids.iter().filter(|o| o.user_id == user_id && o.filled < o.quantity))
-                .cloned(),
-        );
-        orders.extend(
-            self.asks.values()
-                .flat_map(|asks| asks.iter().filter(|o| o.user_id == user_id && o.filled < o.quantity))
-                .cloned(),
-        );
-        orders
-    }
-
-    pub fn match_ask(&mut self, order: &Order) -> Result<(Vec<Fill>, f64), String> {
-        let mut fills = Vec::new();
-        let mut executed_qty = 0.0;
-        
-        // Create a list of price levels to process, sorted by price (highest first)
-        let mut price_levels: Vec<Price> = self.bids.keys().cloned().collect();
-        price_levels.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap()); // Sort in descending order
-        
-        for price in price_levels {
-            if price.0 >= order.price && executed_qty < order.quantity {
-                if let Some(bids) = self.bids.get_mut(&price) {
-                    let remaining_to_fill = order.quantity - executed_qty;
-                    let mut filled_at_this_level = 0.0;
-                    
-                    // Process each bid at this price level
-                    for bid in bids.iter_mut() {
-                        if bid.user_id != order.user_id {
-                            let bid_remaining = bid.quantity - bid.filled;
-                            if bid_remaining > 0.0 {
-                                let fill_qty = f64::min(remaining_to_fill - filled_at_this_level, bid_remaining);
-                                
-                                if fill_qty > 0.0 {
-                                    bid.filled += fill_qty;
-                                    filled_at_this_level += fill_qty;
-                                    
-                                    fills.push(Fill {
-                                        price: price.0,
-                                        qty: fill_qty,
-                                        trade_id: {
-                                            self.last_trade_id += 1;
-                                            self.last_trade_id
-                                        },
-                                        other_user_id: bid.user_id.clone(),
-                                        marker_order_id: bid.order_id.clone(),
-                                    });
-                                    
-                                    if filled_at_this_level >= remaining_to_fill {
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    
-                    executed_qty += filled_at_this_level;
+        self.bids.values()
+            .chain(self.asks.values())
+            .flat_map(|level| self.queue(*level))
+            .filter(|o| o.user_id == user_id && o.filled < o.quantity)
+            .cloned()
+            .collect()
+    }
+
+    /// Orders resting at one price level, in time priority.
+    fn queue(&self, level: PriceLevel) -> impl Iterator<Item = &Order> + '_ {
+        std::iter::successors(level.head, move |&key| self.orders[key].next)
+            .map(move |key| &self.orders[key].order)
+    }
+
+    /// Adds `order` to the back of the queue at its price.
+    fn push_order(&mut self, order: Order) -> usize {
+        let book = match order.side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        let level = book.entry(order.price).or_default();
+        let order_id = order.order_id.clone();
+        let key = self.orders.insert(RestingOrder { order, prev: level.tail, next: None });
+
+        match level.tail {
+            Some(tail) => self.orders[tail].next = Some(key),
+            None => level.head = Some(key),
+        }
+        level.tail = Some(key);
+        level.len += 1;
+        self.order_index.insert(order_id, key);
+        key
+    }
+
+    /// Takes the order stored under `key` out of its queue and the index,
+    /// dropping its price level once it is empty.
+    fn unlink(&mut self, key: usize) -> Order {
+        let RestingOrder { order, prev, next } = self.orders.remove(key);
+        self.order_index.remove(&order.order_id);
+
+        let book = match order.side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        let level = book.get_mut(&order.price).expect("resting order without a price level");
+        match prev {
+            Some(prev) => self.orders[prev].next = next,
+            None => level.head = next,
+        }
+        match next {
+            Some(next) => self.orders[next].prev = prev,
+            None => level.tail = prev,
+        }
+        level.len -= 1;
+        if level.len == 0 {
+            book.remove(&order.price);
+        }
+        order
+    }
+
+    /// Resting liquidity `order` would meet right now as (price, quantity,
+    /// tradable) triples, walked the same way matching would. Orders of the
+    /// same user are not tradable; depending on the self-trade prevention
+    /// mode they end the walk, are skipped, or use up quantity without a fill.
+    fn opposite_liquidity<'a>(&'a self, order: &'a Order) -> Box<dyn Iterator<Item = (Decimal, Decimal, bool)> + 'a> {
+        let levels: Box<dyn Iterator<Item = (&Decimal, &PriceLevel)>> = match order.side {
+            OrderSide::Buy => Box::new(self.asks.iter()),
+            OrderSide::Sell => Box::new(self.bids.iter().rev()),
+        };
+
+        Box::new(levels
+            .take_while(move |(price, _)| match (order.order_type, &order.side) {
+                (OrderType::Market, _) => true,
+                (_, OrderSide::Buy) => **price <= order.price,
+                (_, OrderSide::Sell) => **price >= order.price,
+            })
+            .flat_map(move |(price, level)| {
+                // Pro-rata levels deal with the taker's own orders first
+                let own_first = matches!(self.spec.allocation, Allocation::ProRata { .. });
+                let own = self.queue(*level).filter(move |o| own_first && o.user_id == order.user_id);
+                let others = self.queue(*level).filter(move |o| !(own_first && o.user_id == order.user_id));
+                own.chain(others).map(move |o| (*price, o))
+            })
+            .take_while(move |(_, o)| {
+                o.user_id != order.user_id || matches!(
+                    order.self_trade_prevention,
+                    SelfTradePrevention::CancelOldest | SelfTradePrevention::DecrementAndCancel
+                )
+            })
+            .filter(move |(_, o)| {
+                o.user_id != order.user_id || order.self_trade_prevention != SelfTradePrevention::CancelOldest
+            })
+            .map(move |(price, o)| (price, o.quantity - o.filled, o.user_id != order.user_id)))
+    }
+
+    /// Returns how much of `order` could fill right now and its quote value.
+    pub fn estimate_fill(&self, order: &Order) -> (Decimal, Decimal) {
+        let mut consumed = Decimal::ZERO;
+        let mut quantity = Decimal::ZERO;
+        let mut quote = Decimal::ZERO;
+        for (price, available, tradable) in self.opposite_liquidity(order) {
+            let take = (order.quantity - consumed).min(available);
+            consumed += take;
+            if tradable {
+                quantity += take;
+                quote += take * price;
+            }
+            if consumed == order.quantity {
+                break;
+            }
+        }
+        (quantity, quote)
+    }
+
+    /// Returns how much of a buy `order` can be worked right now without
+    /// spending more than `budget` of the quote asset.
+    pub fn affordable_quantity(&self, order: &Order, budget: Decimal) -> Decimal {
+        let mut quantity = Decimal::ZERO;
+        let mut spent = Decimal::ZERO;
+        for (price, available, tradable) in self.opposite_liquidity(order) {
+            let take = (order.quantity - quantity).min(available);
+            if tradable && spent + take * price > budget {
+                // Whatever the budget buys must still be a whole number of lots
+                return (quantity + (budget - spent) / price).floor_to(self.spec.step_size);
+            }
+            quantity += take;
+            if tradable {
+                spent += take * price;
+            }
+            if quantity == order.quantity {
+                break;
+            }
+        }
+        quantity
+    }
+
+    pub fn last_price(&self) -> Decimal {
+        self.current_price
+    }
+
+    /// Matches `order` against the opposite side of the book, best price
+    /// first. Within a price level the market's allocation decides who
+    /// fills: time priority, or pro-rata to the resting sizes.
+    fn match_order(&mut self, order: &Order) -> Execution {
+        let mut execution = Execution::default();
+        // An amended order may already be partly filled
+        let mut remaining = order.quantity - order.filled;
+
+        // Walk the opposite side from its best price outward. Emptied levels
+        // are dropped as they go, so nothing is left to clean up afterwards.
+        let mut next_price = match order.side {
+            OrderSide::Buy => self.asks.keys().next().copied(),
+            OrderSide::Sell => self.bids.keys().next_back().copied(),
+        };
+
+        while let Some(price) = next_price {
+            let crosses = match order.side {
+                OrderSide::Buy => price <= order.price,
+                OrderSide::Sell => price >= order.price,
+            };
+            if !remaining.is_positive() || !(order.order_type == OrderType::Market || crosses) {
+                break;
+            }
+            match self.spec.allocation {
+                Allocation::PriceTime => self.match_level_fifo(order, price, &mut remaining, &mut execution),
+                Allocation::ProRata { top_priority, min_allocation } => {
+                    self.match_level_pro_rata(order, price, &mut remaining, &mut execution, top_priority, min_allocation)
                 }
             }
+
+            next_price = match order.side {
+                OrderSide::Buy => self.asks.range((Excluded(price), Unbounded)).next().map(|(p, _)| *p),
+                OrderSide::Sell => self.bids.range(..price).next_back().map(|(p, _)| *p),
+            };
         }
-        
-        // Clean up filled orders
-        self.bids.retain(|_price, bids| {
-            bids.retain(|bid| bid.filled < bid.quantity);
-            !bids.is_empty()
-        });
-        
-        Ok((fills, executed_qty))
+
+        execution
     }
 
-    pub fn match_bid(&mut self, order: &Order) -> Result<(Vec<Fill>, f64), String> {
-        let mut fills = Vec::new();
-        let mut executed_qty = 0.0;
+    /// Keys of the orders resting at `price` on the side `order` trades
+    /// against, in time priority.
+    fn opposite_level(&self, order: &Order, price: Decimal) -> Vec<usize> {
+        let level = match order.side {
+            OrderSide::Buy => self.asks.get(&price),
+            OrderSide::Sell => self.bids.get(&price),
+        };
+        std::iter::successors(level.and_then(|level| level.head), |&key| self.orders[key].next).collect()
+    }
 
-        // Create a list of price levels to process, sorted by price (lowest first)
-        let mut price_levels: Vec<Price> = self.asks.keys().cloned().collect();
-        price_levels.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap()); // Sort in ascending order
-        
-        for price in price_levels {
-            if price.0 <= order.price && executed_qty < order.quantity {
-                if let Some(asks) = self.asks.get_mut(&price) {
-                    let remaining_to_fill = order.quantity - executed_qty;
-                    let mut filled_at_this_level = 0.0;
-                    
-                    // Process each ask at this price level
-                    for ask in asks.iter_mut() {
-                        if ask.user_id != order.user_id {
-                            let ask_remaining = ask.quantity - ask.filled;
-                            if ask_remaining > 0.0 {
-                                let fill_qty = f64::min(remaining_to_fill - filled_at_this_level, ask_remaining);
-                                
-                                if fill_qty > 0.0 {
-                                    ask.filled += fill_qty;
-                                    filled_at_this_level += fill_qty;
-                                    
-                                    fills.push(Fill {
-                                        price: price.0,
-                                        qty: fill_qty,
-                                        trade_id: {
-                                            self.last_trade_id += 1;
-                                            self.last_trade_id
-                                        },
-                                        other_user_id: ask.user_id.clone(),
-                                        marker_order_id: ask.order_id.clone(),
-                                    });
-                                    
-                                    if filled_at_this_level >= remaining_to_fill {
-                                        break;
-                                    }
-                                }
-                            }
-                        }
+    /// Fills against the orders resting at `price` in time priority.
+    fn match_level_fifo(&mut self, order: &Order, price: Decimal, remaining: &mut Decimal, execution: &mut Execution) {
+        let level = match order.side {
+            OrderSide::Buy => self.asks.get(&price),
+            OrderSide::Sell => self.bids.get(&price),
+        };
+        let mut cursor = level.and_then(|level| level.head);
+
+        while let Some(key) = cursor {
+            if !remaining.is_positive() {
+                return;
+            }
+            let next = self.orders[key].next;
+            if self.orders[key].order.user_id == order.user_id {
+                self.prevent_self_trade(key, order, remaining, execution);
+                cursor = next;
+                continue;
+            }
+
+            let fill_qty = (*remaining).min(self.orders[key].order.displayed_quantity());
+            self.fill_maker(key, price, fill_qty, execution);
+            *remaining -= fill_qty;
+            // A refreshed iceberg tranche goes to the back of the queue,
+            // where the walk may still reach it
+            cursor = match self.settle_maker(key) {
+                Some(requeued) => next.or(Some(requeued)),
+                None => next,
+            };
+        }
+    }
+
+    /// Shares `remaining` among the orders resting at `price` in proportion
+    /// to their displayed size, rounded down to the step size. With
+    /// `top_priority` the oldest order is filled first; shares below
+    /// `min_allocation` are dropped. Whatever rounding leaves over goes out
+    /// in time priority. Self-trade prevention deals with the taker's own
+    /// orders at the level before anything is shared out.
+    fn match_level_pro_rata(
+        &mut self,
+        order: &Order,
+        price: Decimal,
+        remaining: &mut Decimal,
+        execution: &mut Execution,
+        top_priority: bool,
+        min_allocation: Decimal,
+    ) {
+        for key in self.opposite_level(order, price) {
+            if remaining.is_positive() && self.orders[key].order.user_id == order.user_id {
+                self.prevent_self_trade(key, order, remaining, execution);
+            }
+        }
+
+        // Icebergs refresh once their tranche is used up, so keep sharing
+        // until the taker or the level runs out
+        let mut first_round = true;
+        while remaining.is_positive() {
+            let makers: Vec<(usize, Decimal)> = self.opposite_level(order, price)
+                .into_iter()
+                .map(|key| (key, self.orders[key].order.displayed_quantity()))
+                .filter(|&(key, displayed)| displayed.is_positive() && self.orders[key].order.user_id != order.user_id)
+                .collect();
+            if makers.is_empty() {
+                return;
+            }
+
+            let mut allocations = vec![Decimal::ZERO; makers.len()];
+            let mut left = *remaining;
+            if top_priority && first_round {
+                allocations[0] = left.min(makers[0].1);
+                left -= allocations[0];
+            }
+
+            let total: Decimal = makers.iter().zip(&allocations).map(|(&(_, displayed), allocated)| displayed - *allocated).sum();
+            if total.is_positive() {
+                let mut shared = Decimal::ZERO;
+                for (&(_, displayed), allocated) in makers.iter().zip(allocations.iter_mut()) {
+                    let capacity = displayed - *allocated;
+                    // On very large levels the exact product overflows; taking
+                    // the ratio first is a little less precise and whatever it
+                    // leaves over goes in time priority below
+                    let share = left.checked_mul(capacity)
+                        .and_then(|product| product.checked_div(total))
+                        .or_else(|| left.checked_div(total).and_then(|ratio| ratio.checked_mul(capacity)))
+                        .unwrap_or(Decimal::ZERO);
+                    let share = share.floor_to(self.spec.step_size).min(capacity);
+                    if share >= min_allocation {
+                        *allocated += share;
+                        shared += share;
                     }
-                    
-                    executed_qty += filled_at_this_level;
                 }
+                left -= shared;
+            }
+            for (&(_, displayed), allocated) in makers.iter().zip(allocations.iter_mut()) {
+                let take = left.min(displayed - *allocated);
+                *allocated += take;
+                left -= take;
+            }
+
+            for (&(key, _), &allocated) in makers.iter().zip(&allocations) {
+                self.fill_maker(key, price, allocated, execution);
+                *remaining -= allocated;
+            }
+            for &(key, _) in &makers {
+                self.settle_maker(key);
             }
+            first_round = false;
         }
-        
-        // Clean up filled orders
-        self.asks.retain(|_price, asks| {
-            asks.retain(|ask| ask.filled < ask.quantity);
-            !asks.is_empty()
-        });
-        
-        Ok((fills, executed_qty))
     }
 
-    pub fn cancel_bid(&mut self, order_id: &str) -> Result<f64, String> {
-        let price = self.bids.iter()
-            .find_map(|(price, bids)| bids.iter().position(|bid| bid.order_id == order_id).map(|_| price.0))
-            .ok_or("Order not found")?;
-        
-        self.bids.entry(Price(price)).and_modify(|bids| {
-            bids.retain(|bid| bid.order_id != order_id);
-        });
-        Ok(price)                            
+    /// Applies the taker's self-trade prevention mode to its own resting
+    /// order under `key`.
+    fn prevent_self_trade(&mut self, key: usize, order: &Order, remaining: &mut Decimal, execution: &mut Execution) {
+        let maker = &mut self.orders[key].order;
+        let maker_remaining = maker.quantity - maker.filled;
+        let (maker_cancelled, taker_cancelled) = match order.self_trade_prevention {
+            SelfTradePrevention::CancelNewest => (Decimal::ZERO, *remaining),
+            SelfTradePrevention::CancelOldest => (maker_remaining, Decimal::ZERO),
+            SelfTradePrevention::CancelBoth => (maker_remaining, *remaining),
+            SelfTradePrevention::DecrementAndCancel => {
+                let decrement = (*remaining).min(maker_remaining);
+                (decrement, decrement)
+            }
+        };
+
+        if maker_cancelled.is_positive() {
+            maker.quantity -= maker_cancelled;
+            execution.cancelled_makers.push((maker.clone(), maker_cancelled));
+            if maker.filled >= maker.quantity {
+                self.unlink(key);
+            }
+        }
+        *remaining -= taker_cancelled;
+        execution.self_trade_cancelled_qty += taker_cancelled;
     }
 
-    pub fn cancel_ask(&mut self, order_id: &str) -> Result<f64, String> {
-        let price = self.asks.iter()
-            .find_map(|(price, asks)| asks.iter().position(|ask| ask.order_id == order_id).map(|_| price.0))
-            .ok_or("Order not found")?;
-        
-        self.asks.entry(Price(price)).and_modify(|asks| {
-            asks.retain(|ask| ask.order_id != order_id);
+    /// Fills `qty` of the resting order under `key` at `price`.
+    fn fill_maker(&mut self, key: usize, price: Decimal, qty: Decimal, execution: &mut Execution) {
+        if !qty.is_positive() {
+            return;
+        }
+        let maker = &mut self.orders[key].order;
+        maker.fill_resting(qty);
+        execution.executed_qty += qty;
+
+        // Fills of every tranche are reported against the parent order
+        self.last_trade_id += 1;
+        execution.fills.push(Fill {
+            price,
+            qty,
+            trade_id: self.last_trade_id,
+            other_user_id: maker.user_id.clone(),
+            marker_order_id: maker.order_id.clone(),
+            fee: Decimal::ZERO,
+            fee_asset: String::new(),
+            maker_fee: Decimal::ZERO,
+            maker_fee_asset: String::new(),
         });
-        Ok(price)                           
+        match execution.makers.iter_mut().find(|m| m.order_id == maker.order_id) {
+            Some(m) => *m = maker.clone(),
+            None => execution.makers.push(maker.clone()),
+        }
+    }
+
+    /// Takes a filled order off the book, or loads the next tranche of an
+    /// iceberg whose visible one is used up. A refreshed tranche loses time
+    /// priority; its new key is returned.
+    fn settle_maker(&mut self, key: usize) -> Option<usize> {
+        let maker = &mut self.orders[key].order;
+        if maker.filled >= maker.quantity {
+            self.unlink(key);
+            None
+        } else if maker.refresh_tranche() {
+            let refreshed = self.unlink(key);
+            Some(self.push_order(refreshed))
+        } else {
+            None
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::trade::orderbook::{OrderSide, Order};
+    use crate::trade::orderbook::{OrderSide, Order, OrderType, TimeInForce};
     use rand::thread_rng;
     use rand::distributions::{Alphanumeric, DistString};
 
@@ -307,21 +877,43 @@ mod tests {
         Alphanumeric.sample_string(&mut thread_rng(), 24)
     }
 
+    fn limit_order(user_id: &str, side: OrderSide, price: i64, quantity: i64) -> Order {
+        Order {
+            order_id: generate_order_id(),
+            user_id: user_id.to_string(),
+            price: Decimal::from_int(price),
+            quantity: Decimal::from_int(quantity),
+            filled: Decimal::ZERO,
+            side,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            post_only: None,
+            stop_price: None,
+            iceberg: None,
+            self_trade_prevention: SelfTradePrevention::CancelNewest,
+            expires_at: None,
+            client_order_id: None,
+        }
+    }
+
+    fn resting_orders(orderbook: &Orderbook, side: OrderSide, price: i64) -> Vec<Order> {
+        let book = match side {
+            OrderSide::Buy => &orderbook.bids,
+            OrderSide::Sell => &orderbook.asks,
+        };
+        book.get(&Decimal::from_int(price))
+            .map(|level| orderbook.queue(*level).cloned().collect())
+            .unwrap_or_default()
+    }
+
     #[test]
     fn test_add_bid_order() {
         let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
-        let mut order = Order {
-            order_id: generate_order_id(),
-            user_id: "user1".to_string(),
-            price: 100.0,
-            quantity: 10.0,
-            filled: 0.0,
-            side: OrderSide::Buy,
-        };
+        let mut order = limit_order("user1", OrderSide::Buy, 100, 10);
 
-        let (fills, executed_qty) = orderbook.add_order(&mut order).unwrap();
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut order).unwrap();
         assert_eq!(fills.len(), 0);
-        assert_eq!(executed_qty, 0.0);
+        assert_eq!(executed_qty, Decimal::ZERO);
         assert_eq!(orderbook.bids.len(), 1);
         assert_eq!(orderbook.asks.len(), 0);
     }
@@ -329,18 +921,11 @@ mod tests {
     #[test]
     fn test_add_ask_order() {
         let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
-        let mut order = Order {
-            order_id: generate_order_id(),
-            user_id: "user1".to_string(),
-            price: 100.0,
-            quantity: 10.0,
-            filled: 0.0,
-            side: OrderSide::Sell,
-        };
+        let mut order = limit_order("user1", OrderSide::Sell, 100, 10);
 
-        let (fills, executed_qty) = orderbook.add_order(&mut order).unwrap();
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut order).unwrap();
         assert_eq!(fills.len(), 0);
-        assert_eq!(executed_qty, 0.0);
+        assert_eq!(executed_qty, Decimal::ZERO);
         assert_eq!(orderbook.bids.len(), 0);
         assert_eq!(orderbook.asks.len(), 1);
     }
@@ -350,32 +935,18 @@ mod tests {
         let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
         
         // Add a buy order
-        let mut buy_order = Order {
-            order_id: generate_order_id(),
-            user_id: "user1".to_string(),
-            price: 100.0,
-            quantity: 5.0,
-            filled: 0.0,
-            side: OrderSide::Buy,
-        };
+        let mut buy_order = limit_order("user1", OrderSide::Buy, 100, 5);
         
-        let (fills, executed_qty) = orderbook.add_order(&mut buy_order).unwrap();
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut buy_order).unwrap();
         assert_eq!(fills.len(), 0);
-        assert_eq!(executed_qty, 0.0);
+        assert_eq!(executed_qty, Decimal::ZERO);
         
         // Add a matching sell order from a different user
-        let mut sell_order = Order {
-            order_id: generate_order_id(),
-            user_id: "user2".to_string(),
-            price: 100.0,
-            quantity: 5.0,
-            filled: 0.0,
-            side: OrderSide::Sell,
-        };
+        let mut sell_order = limit_order("user2", OrderSide::Sell, 100, 5);
         
-        let (fills, executed_qty) = orderbook.add_order(&mut sell_order).unwrap();
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut sell_order).unwrap();
         assert_eq!(fills.len(), 1);
-        assert_eq!(executed_qty, 5.0);
+        assert_eq!(executed_qty, Decimal::from_int(5));
         assert_eq!(orderbook.bids.len(), 0); // Buy order should be fully matched and removed
         assert_eq!(orderbook.asks.len(), 0); // Sell order should be fully matched and not added
     }
@@ -385,34 +956,21 @@ mod tests {
         let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
         
         // Add a buy order
-        let mut buy_order = Order {
-            order_id: generate_order_id(),
-            user_id: "user1".to_string(),
-            price: 100.0,
-            quantity: 5.0,
-            filled: 0.0,
-            side: OrderSide::Buy,
-        };
+        let mut buy_order = limit_order("user1", OrderSide::Buy, 100, 5);
         
-        let (fills, executed_qty) = orderbook.add_order(&mut buy_order).unwrap();
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut buy_order).unwrap();
         assert_eq!(fills.len(), 0);
-        assert_eq!(executed_qty, 0.0);
+        assert_eq!(executed_qty, Decimal::ZERO);
         
         // Add a matching sell order from the same user
-        let mut sell_order = Order {
-            order_id: generate_order_id(),
-            user_id: "user1".to_string(),
-            price: 100.0,
-            quantity: 5.0,
-            filled: 0.0,
-            side: OrderSide::Sell,
-        };
+        let mut sell_order = limit_order("user1", OrderSide::Sell, 100, 5);
         
-        let (fills, executed_qty) = orderbook.add_order(&mut sell_order).unwrap();
+        let Execution { fills, executed_qty, self_trade_cancelled_qty, .. } = orderbook.add_order(&mut sell_order).unwrap();
         assert_eq!(fills.len(), 0); // No fills because same user
-        assert_eq!(executed_qty, 0.0);
+        assert_eq!(executed_qty, Decimal::ZERO);
+        assert_eq!(self_trade_cancelled_qty, Decimal::from_int(5)); // Cancel newest by default
         assert_eq!(orderbook.bids.len(), 1); // Buy order should remain
-        assert_eq!(orderbook.asks.len(), 1); // Sell order should be added
+        assert_eq!(orderbook.asks.len(), 0); // Sell order must not rest and cross the book
     }
 
     #[test]
@@ -420,38 +978,25 @@ mod tests {
         let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
         
         // Add a buy order
-        let mut buy_order = Order {
-            order_id: generate_order_id(),
-            user_id: "user1".to_string(),
-            price: 100.0,
-            quantity: 10.0,
-            filled: 0.0,
-            side: OrderSide::Buy,
-        };
+        let mut buy_order = limit_order("user1", OrderSide::Buy, 100, 10);
         
-        let (fills, executed_qty) = orderbook.add_order(&mut buy_order).unwrap();
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut buy_order).unwrap();
         assert_eq!(fills.len(), 0);
-        assert_eq!(executed_qty, 0.0);
+        assert_eq!(executed_qty, Decimal::ZERO);
         
         // Add a smaller matching sell order
-        let mut sell_order = Order {
-            order_id: generate_order_id(),
-            user_id: "user2".to_string(),
-            price: 100.0,
-            quantity: 5.0,
-            filled: 0.0,
-            side: OrderSide::Sell,
-        };
+        let mut sell_order = limit_order("user2", OrderSide::Sell, 100, 5);
         
-        let (fills, executed_qty) = orderbook.add_order(&mut sell_order).unwrap();
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut sell_order).unwrap();
         assert_eq!(fills.len(), 1);
-        assert_eq!(executed_qty, 5.0);
+        assert_eq!(executed_qty, Decimal::from_int(5));
         assert_eq!(orderbook.bids.len(), 1); // Buy order should remain with reduced quantity
         assert_eq!(orderbook.asks.len(), 0); // Sell order should be fully matched and not added
         
         // Check remaining buy order quantity
-        let remaining_qty = orderbook.bids.values().next().unwrap()[0].quantity - orderbook.bids.values().next().unwrap()[0].filled;
-        assert_eq!(remaining_qty, 5.0);
+        let resting = resting_orders(&orderbook, OrderSide::Buy, 100);
+        let remaining_qty = resting[0].quantity - resting[0].filled;
+        assert_eq!(remaining_qty, Decimal::from_int(5));
     }
 
     #[test]
@@ -459,41 +1004,583 @@ mod tests {
         let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
         
         // Add buy orders at different prices
-        let mut buy_order1 = Order {
-            order_id: generate_order_id(),
-            user_id: "user1".to_string(),
-            price: 100.0,
-            quantity: 5.0,
-            filled: 0.0,
-            side: OrderSide::Buy,
-        };
-        
-        let mut buy_order2 = Order {
-            order_id: generate_order_id(),
-            user_id: "user2".to_string(),
-            price: 102.0,
-            quantity: 5.0,
-            filled: 0.0,
-            side: OrderSide::Buy,
-        };
+        let mut buy_order1 = limit_order("user1", OrderSide::Buy, 100, 5);
+        let mut buy_order2 = limit_order("user2", OrderSide::Buy, 102, 5);
         
         orderbook.add_order(&mut buy_order1).unwrap();
         orderbook.add_order(&mut buy_order2).unwrap();
         
         // Add a matching sell order
-        let mut sell_order = Order {
-            order_id: generate_order_id(),
-            user_id: "user3".to_string(),
-            price: 99.0,
-            quantity: 5.0,
-            filled: 0.0,
-            side: OrderSide::Sell,
-        };
+        let mut sell_order = limit_order("user3", OrderSide::Sell, 99, 5);
         
-        let (fills, executed_qty) = orderbook.add_order(&mut sell_order).unwrap();
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut sell_order).unwrap();
         assert_eq!(fills.len(), 1);
-        assert_eq!(executed_qty, 5.0);
-        assert_eq!(fills[0].price, 102.0); // Should match with the higher priced buy order
+        assert_eq!(executed_qty, Decimal::from_int(5));
+        assert_eq!(fills[0].price, Decimal::from_int(102)); // Should match with the higher priced buy order
         assert_eq!(orderbook.bids.len(), 1); // Only the lower priced buy order should remain
     }
+
+    #[test]
+    fn test_market_order_sweeps_book() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+
+        for (price, user) in [(100, "user1"), (101, "user2")] {
+            orderbook.add_order(&mut limit_order(user, OrderSide::Sell, price, 2)).unwrap();
+        }
+
+        // Buy more than the book holds
+        let mut market_buy = limit_order("user3", OrderSide::Buy, 0, 5);
+        market_buy.order_type = OrderType::Market;
+
+        let (quantity, quote) = orderbook.estimate_fill(&market_buy);
+        assert_eq!(quantity, Decimal::from_int(4));
+        assert_eq!(quote, Decimal::from_int(402));
+
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut market_buy).unwrap();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(executed_qty, Decimal::from_int(4));
+        assert_eq!(fills[0].price, Decimal::from_int(100));
+        assert_eq!(fills[1].price, Decimal::from_int(101));
+        assert_eq!(orderbook.asks.len(), 0); // Book is exhausted
+        assert_eq!(orderbook.bids.len(), 0); // Market order never rests
+    }
+
+    #[test]
+    fn test_ioc_cancels_unfilled_remainder() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 100, 3)).unwrap();
+
+        let mut ioc_buy = limit_order("user2", OrderSide::Buy, 100, 5);
+        ioc_buy.time_in_force = TimeInForce::Ioc;
+
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut ioc_buy).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(executed_qty, Decimal::from_int(3));
+        assert_eq!(orderbook.asks.len(), 0);
+        assert_eq!(orderbook.bids.len(), 0); // Remainder is not rested
+    }
+
+    #[test]
+    fn test_fok_rejects_without_touching_book() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 100, 3)).unwrap();
+
+        let mut fok_buy = limit_order("user2", OrderSide::Buy, 100, 5);
+        fok_buy.time_in_force = TimeInForce::Fok;
+
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut fok_buy).unwrap();
+        assert_eq!(fills.len(), 0);
+        assert_eq!(executed_qty, Decimal::ZERO);
+        assert_eq!(orderbook.bids.len(), 0);
+        let resting = &resting_orders(&orderbook, OrderSide::Sell, 100)[0];
+        assert_eq!(resting.filled, Decimal::ZERO); // Nothing was partially applied
+    }
+
+    #[test]
+    fn test_fok_fills_across_levels() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 100, 3)).unwrap();
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 101, 3)).unwrap();
+
+        let mut fok_buy = limit_order("user2", OrderSide::Buy, 101, 5);
+        fok_buy.time_in_force = TimeInForce::Fok;
+
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut fok_buy).unwrap();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(executed_qty, Decimal::from_int(5));
+        assert_eq!(orderbook.bids.len(), 0);
+        assert_eq!(orderbook.asks.len(), 1);
+    }
+
+    #[test]
+    fn test_post_only_rejects_crossing_order() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 100, 3)).unwrap();
+
+        let mut post_only_buy = limit_order("user2", OrderSide::Buy, 100, 1);
+        post_only_buy.post_only = Some(PostOnly::Reject);
+        let result = orderbook.add_order(&mut post_only_buy);
+        assert_eq!(result.unwrap_err(), RejectReason::PostOnlyWouldCross);
+        assert_eq!(orderbook.bids.len(), 0);
+        assert_eq!(resting_orders(&orderbook, OrderSide::Sell, 100)[0].filled, Decimal::ZERO);
+
+        // A non-crossing post-only order rests normally
+        let mut passive_buy = limit_order("user2", OrderSide::Buy, 99, 1);
+        passive_buy.post_only = Some(PostOnly::Reject);
+        let Execution { fills, .. } = orderbook.add_order(&mut passive_buy).unwrap();
+        assert_eq!(fills.len(), 0);
+        assert_eq!(orderbook.bids.len(), 1);
+    }
+
+    #[test]
+    fn test_post_only_reprices_behind_best() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        orderbook.spec.tick_size = "0.01".parse().unwrap();
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Buy, 100, 3)).unwrap();
+
+        let mut post_only_sell = limit_order("user2", OrderSide::Sell, 99, 1);
+        post_only_sell.post_only = Some(PostOnly::Reprice);
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut post_only_sell).unwrap();
+        assert_eq!(fills.len(), 0);
+        assert_eq!(executed_qty, Decimal::ZERO);
+        assert_eq!(post_only_sell.price, "100.01".parse().unwrap());
+        assert!(orderbook.asks.contains_key(&"100.01".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_affordable_quantity_and_last_price() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 100, 2)).unwrap();
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 200, 2)).unwrap();
+        assert_eq!(orderbook.last_price(), Decimal::ZERO);
+
+        let mut market_buy = limit_order("user2", OrderSide::Buy, 0, 4);
+        market_buy.order_type = OrderType::Market;
+        // 200 buys the first level, the remaining 100 buys half a unit at 200
+        assert_eq!(orderbook.affordable_quantity(&market_buy, Decimal::from_int(300)), "2.5".parse().unwrap());
+
+        orderbook.add_order(&mut limit_order("user2", OrderSide::Buy, 100, 1)).unwrap();
+        assert_eq!(orderbook.last_price(), Decimal::from_int(100));
+    }
+
+    #[test]
+    fn test_affordable_quantity_rounds_down_to_step_size() {
+        let mut orderbook = Orderbook::with_spec("TEST_MARKET".to_string(), MarketSpec {
+            step_size: Decimal::from_int(1),
+            ..MarketSpec::default()
+        });
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 100, 2)).unwrap();
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 200, 2)).unwrap();
+
+        let mut market_buy = limit_order("user2", OrderSide::Buy, 0, 4);
+        market_buy.order_type = OrderType::Market;
+        // Half a unit at 200 is affordable, but not a whole lot
+        assert_eq!(orderbook.affordable_quantity(&market_buy, Decimal::from_int(300)), Decimal::from_int(2));
+    }
+
+    fn iceberg_order(user_id: &str, side: OrderSide, price: i64, quantity: i64, display_quantity: i64) -> Order {
+        let mut order = limit_order(user_id, side, price, quantity);
+        order.iceberg = Some(Iceberg {
+            display_quantity: Decimal::from_int(display_quantity),
+            displayed: Decimal::from_int(display_quantity),
+        });
+        order
+    }
+
+    #[test]
+    fn test_iceberg_depth_shows_only_displayed_tranche() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        orderbook.add_order(&mut iceberg_order("user1", OrderSide::Sell, 100, 10, 2)).unwrap();
+        orderbook.add_order(&mut limit_order("user2", OrderSide::Sell, 100, 1)).unwrap();
+
+        let depth = orderbook.get_depth();
+        assert_eq!(depth.asks, vec![("100".to_string(), "3".to_string())]);
+
+        // The hidden reserve can still be traded against
+        let mut buy = limit_order("user3", OrderSide::Buy, 100, 11);
+        buy.time_in_force = TimeInForce::Fok;
+        assert_eq!(orderbook.estimate_fill(&buy).0, Decimal::from_int(11));
+    }
+
+    #[test]
+    fn test_iceberg_refresh_loses_time_priority() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        let mut iceberg = iceberg_order("user1", OrderSide::Sell, 100, 5, 2);
+        let mut plain = limit_order("user2", OrderSide::Sell, 100, 1);
+        orderbook.add_order(&mut iceberg).unwrap();
+        orderbook.add_order(&mut plain).unwrap();
+
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut limit_order("user3", OrderSide::Buy, 100, 4)).unwrap();
+        assert_eq!(executed_qty, Decimal::from_int(4));
+
+        // First tranche, then the order queued behind it, then the refreshed tranche
+        let makers: Vec<(&str, Decimal)> = fills.iter().map(|f| (f.marker_order_id.as_str(), f.qty)).collect();
+        assert_eq!(makers, vec![
+            (iceberg.order_id.as_str(), Decimal::from_int(2)),
+            (plain.order_id.as_str(), Decimal::from_int(1)),
+            (iceberg.order_id.as_str(), Decimal::from_int(1)),
+        ]);
+
+        // One unit of the refreshed tranche is left showing, one more stays hidden
+        let resting = resting_orders(&orderbook, OrderSide::Sell, 100);
+        assert_eq!(resting.len(), 1);
+        assert_eq!(resting[0].displayed_quantity(), Decimal::from_int(1));
+        assert_eq!(orderbook.get_depth().asks, vec![("100".to_string(), "1".to_string())]);
+    }
+
+    #[test]
+    fn test_execution_reports_each_maker_once_as_left() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        let mut iceberg = limit_order("user1", OrderSide::Sell, 100, 5);
+        iceberg.iceberg = Some(Iceberg { display_quantity: Decimal::from_int(2), displayed: Decimal::from_int(2) });
+        let mut plain = limit_order("user2", OrderSide::Sell, 100, 1);
+        orderbook.add_order(&mut iceberg).unwrap();
+        orderbook.add_order(&mut plain).unwrap();
+
+        let Execution { makers, .. } = orderbook.add_order(&mut limit_order("user3", OrderSide::Buy, 100, 4)).unwrap();
+        let statuses: Vec<(&str, Decimal, OrderStatus)> = makers.iter()
+            .map(|m| (m.order_id.as_str(), m.filled, m.fill_status()))
+            .collect();
+        assert_eq!(statuses, vec![
+            (iceberg.order_id.as_str(), Decimal::from_int(3), OrderStatus::PartiallyFilled),
+            (plain.order_id.as_str(), Decimal::from_int(1), OrderStatus::Filled),
+        ]);
+    }
+
+    #[test]
+    fn test_reduce_order_keeps_queue_position() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        let mut first = limit_order("user1", OrderSide::Sell, 100, 5);
+        let mut second = limit_order("user2", OrderSide::Sell, 100, 5);
+        orderbook.add_order(&mut first).unwrap();
+        orderbook.add_order(&mut second).unwrap();
+
+        orderbook.reduce_order(&first.order_id, Decimal::from_int(2)).unwrap();
+
+        let Execution { fills, .. } = orderbook.add_order(&mut limit_order("user3", OrderSide::Buy, 100, 3)).unwrap();
+        assert_eq!(fills[0].marker_order_id, first.order_id);
+        assert_eq!(fills[0].qty, Decimal::from_int(2));
+        assert_eq!(fills[1].marker_order_id, second.order_id);
+        assert_eq!(fills[1].qty, Decimal::from_int(1));
+    }
+
+    #[test]
+    fn test_reentered_order_only_fills_its_remainder() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        let mut bid = limit_order("user1", OrderSide::Buy, 100, 5);
+        orderbook.add_order(&mut bid).unwrap();
+        orderbook.add_order(&mut limit_order("user2", OrderSide::Sell, 100, 2)).unwrap();
+        orderbook.add_order(&mut limit_order("user2", OrderSide::Sell, 101, 10)).unwrap();
+
+        // Re-enter the partly filled bid at a price that crosses the remaining ask
+        let mut amended = orderbook.remove_order(&bid.order_id).unwrap();
+        assert!(orderbook.bids.is_empty());
+        amended.price = Decimal::from_int(101);
+
+        let Execution { fills, executed_qty, .. } = orderbook.add_order(&mut amended).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(executed_qty, Decimal::from_int(3));
+        assert_eq!(amended.filled, Decimal::from_int(5));
+        assert!(orderbook.bids.is_empty());
+        assert_eq!(orderbook.get_depth().asks, vec![("101".to_string(), "7".to_string())]);
+    }
+
+    fn self_trade_book() -> (Orderbook, Order) {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        let mut own_ask = limit_order("user1", OrderSide::Sell, 100, 2);
+        orderbook.add_order(&mut own_ask).unwrap();
+        orderbook.add_order(&mut limit_order("user2", OrderSide::Sell, 100, 2)).unwrap();
+        (orderbook, own_ask)
+    }
+
+    fn self_trading_buy(mode: SelfTradePrevention) -> Order {
+        let mut buy = limit_order("user1", OrderSide::Buy, 100, 3);
+        buy.self_trade_prevention = mode;
+        buy
+    }
+
+    fn assert_not_crossed(orderbook: &Orderbook) {
+        if let (Some(best_bid), Some(best_ask)) = (orderbook.bids.keys().next_back(), orderbook.asks.keys().next()) {
+            assert!(best_bid < best_ask, "book is crossed: {} >= {}", best_bid, best_ask);
+        }
+    }
+
+    #[test]
+    fn test_self_trade_cancel_oldest() {
+        let (mut orderbook, own_ask) = self_trade_book();
+        let mut buy = self_trading_buy(SelfTradePrevention::CancelOldest);
+
+        let execution = orderbook.add_order(&mut buy).unwrap();
+        assert_eq!(execution.executed_qty, Decimal::from_int(2));
+        assert_eq!(execution.cancelled_makers.len(), 1);
+        assert_eq!(execution.cancelled_makers[0].0.order_id, own_ask.order_id);
+        assert_eq!(execution.cancelled_makers[0].1, Decimal::from_int(2));
+        assert_eq!(orderbook.get_depth().bids, vec![("100".to_string(), "1".to_string())]);
+        assert!(orderbook.asks.is_empty());
+        assert_not_crossed(&orderbook);
+    }
+
+    #[test]
+    fn test_self_trade_cancel_both() {
+        let (mut orderbook, _) = self_trade_book();
+        let mut buy = self_trading_buy(SelfTradePrevention::CancelBoth);
+
+        let execution = orderbook.add_order(&mut buy).unwrap();
+        assert_eq!(execution.executed_qty, Decimal::ZERO);
+        assert_eq!(execution.self_trade_cancelled_qty, Decimal::from_int(3));
+        assert_eq!(execution.cancelled_makers.len(), 1);
+        assert!(orderbook.bids.is_empty());
+        assert_eq!(orderbook.get_depth().asks, vec![("100".to_string(), "2".to_string())]);
+        assert_not_crossed(&orderbook);
+    }
+
+    #[test]
+    fn test_self_trade_decrement_and_cancel() {
+        let (mut orderbook, _) = self_trade_book();
+        let mut buy = self_trading_buy(SelfTradePrevention::DecrementAndCancel);
+
+        // Both orders lose 2, the resting one is gone and 1 left trades with user2
+        let execution = orderbook.add_order(&mut buy).unwrap();
+        assert_eq!(execution.self_trade_cancelled_qty, Decimal::from_int(2));
+        assert_eq!(execution.cancelled_makers[0].1, Decimal::from_int(2));
+        assert_eq!(execution.executed_qty, Decimal::from_int(1));
+        assert_eq!(buy.quantity, Decimal::from_int(1));
+        assert!(orderbook.bids.is_empty());
+        assert_eq!(orderbook.get_depth().asks, vec![("100".to_string(), "1".to_string())]);
+        assert_not_crossed(&orderbook);
+    }
+
+    #[test]
+    fn test_fok_counts_self_trade_as_unfilled() {
+        let (mut orderbook, _) = self_trade_book();
+        let mut buy = self_trading_buy(SelfTradePrevention::CancelOldest);
+        buy.time_in_force = TimeInForce::Fok;
+        // Only user2's 2 are available to a cancel-oldest order
+        assert_eq!(orderbook.estimate_fill(&buy).0, Decimal::from_int(2));
+
+        let execution = orderbook.add_order(&mut buy).unwrap();
+        assert!(execution.fills.is_empty());
+        assert_eq!(orderbook.get_depth().asks, vec![("100".to_string(), "4".to_string())]);
+    }
+
+    #[test]
+    fn test_cancel_from_middle_of_level_keeps_queue() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        let mut orders: Vec<Order> = (0..3).map(|_| limit_order("user1", OrderSide::Buy, 100, 1)).collect();
+        for order in &mut orders {
+            orderbook.add_order(order).unwrap();
+        }
+
+        let removed = orderbook.remove_order(&orders[1].order_id).unwrap();
+        assert_eq!(removed.order_id, orders[1].order_id);
+        assert!(orderbook.get_order(&orders[1].order_id).is_none());
+        assert!(orderbook.remove_order(&orders[1].order_id).is_none());
+
+        let queue: Vec<String> = resting_orders(&orderbook, OrderSide::Buy, 100).into_iter().map(|o| o.order_id).collect();
+        assert_eq!(queue, vec![orders[0].order_id.clone(), orders[2].order_id.clone()]);
+
+        // The remaining links still give time priority
+        let Execution { fills, .. } = orderbook.add_order(&mut limit_order("user2", OrderSide::Sell, 100, 2)).unwrap();
+        assert_eq!(fills[0].marker_order_id, orders[0].order_id);
+        assert_eq!(fills[1].marker_order_id, orders[2].order_id);
+        assert!(orderbook.bids.is_empty());
+        assert!(orderbook.order_index.is_empty());
+    }
+
+    #[test]
+    fn test_index_follows_fills_and_snapshots() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        let mut filled = limit_order("user1", OrderSide::Sell, 100, 1);
+        let mut partial = limit_order("user1", OrderSide::Sell, 101, 2);
+        orderbook.add_order(&mut filled).unwrap();
+        orderbook.add_order(&mut partial).unwrap();
+        orderbook.add_order(&mut limit_order("user2", OrderSide::Buy, 101, 2)).unwrap();
+
+        assert!(orderbook.get_order(&filled.order_id).is_none());
+        assert_eq!(orderbook.get_order(&partial.order_id).unwrap().filled, Decimal::from_int(1));
+
+        let restored: Orderbook = serde_json::from_str(&serde_json::to_string(&orderbook).unwrap()).unwrap();
+        assert_eq!(restored.get_order(&partial.order_id).unwrap().filled, Decimal::from_int(1));
+        assert_eq!(restored.get_depth().asks, vec![("101".to_string(), "1".to_string())]);
+    }
+
+    #[test]
+    fn test_uncross_trades_at_single_price() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        let mut first_buy = limit_order("user1", OrderSide::Buy, 102, 3);
+        for order in [
+            &mut first_buy,
+            &mut limit_order("user2", OrderSide::Buy, 101, 2),
+            &mut limit_order("user3", OrderSide::Buy, 99, 5),
+            &mut limit_order("user4", OrderSide::Sell, 98, 2),
+            &mut limit_order("user5", OrderSide::Sell, 100, 2),
+            &mut iceberg_order("user6", OrderSide::Sell, 101, 4, 1),
+        ] {
+            orderbook.rest_order(order);
+        }
+
+        let (bids, asks) = orderbook.level_totals();
+        assert_eq!(asks[2], (Decimal::from_int(101), Decimal::from_int(4)));
+        let price = crate::trade::auction::equilibrium(&bids, &asks, Decimal::ZERO).unwrap().price;
+        let uncross = orderbook.uncross(price);
+
+        let fills: Vec<&Fill> = uncross.trades.iter().flat_map(|(_, fills)| fills).collect();
+        assert!(fills.iter().all(|f| f.price == Decimal::from_int(101)));
+        assert_eq!(fills.iter().map(|f| f.qty).sum::<Decimal>(), Decimal::from_int(5));
+        assert_eq!(uncross.trades[0].0.order_id, first_buy.order_id);
+        assert_eq!(uncross.trades[0].0.filled, Decimal::from_int(3));
+        assert_eq!(orderbook.last_price(), Decimal::from_int(101));
+
+        // The iceberg traded one unit and shows a fresh tranche of its reserve
+        let left = resting_orders(&orderbook, OrderSide::Sell, 101);
+        assert_eq!(left[0].filled, Decimal::from_int(1));
+        assert_eq!(left[0].displayed_quantity(), Decimal::from_int(1));
+        assert_eq!(resting_orders(&orderbook, OrderSide::Buy, 99).len(), 1);
+        assert_not_crossed(&orderbook);
+    }
+
+    #[test]
+    fn test_uncross_cancels_same_user_overlap() {
+        let mut orderbook = Orderbook::new("TEST_MARKET".to_string());
+        orderbook.rest_order(&mut limit_order("user1", OrderSide::Buy, 100, 3));
+        orderbook.rest_order(&mut limit_order("user1", OrderSide::Sell, 100, 2));
+        orderbook.rest_order(&mut limit_order("user2", OrderSide::Sell, 100, 2));
+
+        let uncross = orderbook.uncross(Decimal::from_int(100));
+        assert_eq!(uncross.cancelled.len(), 2);
+        assert!(uncross.cancelled.iter().all(|(_, qty)| *qty == Decimal::from_int(2)));
+        assert_eq!(uncross.trades.len(), 1);
+        assert_eq!(uncross.trades[0].1[0].other_user_id, "user2");
+        assert_eq!(uncross.trades[0].1[0].qty, Decimal::from_int(1));
+        assert_eq!(resting_orders(&orderbook, OrderSide::Sell, 100)[0].filled, Decimal::from_int(1));
+        assert_not_crossed(&orderbook);
+    }
+
+    #[test]
+    fn test_batch_auction_book_only_collects_orders() {
+        let mut orderbook = Orderbook::with_spec("TEST_MARKET".to_string(), MarketSpec {
+            matching: MatchingMode::BatchAuction { interval_ms: 1_000 },
+            ..MarketSpec::default()
+        });
+        orderbook.add_order(&mut limit_order("user1", OrderSide::Sell, 100, 2)).unwrap();
+        let execution = orderbook.add_order(&mut limit_order("user2", OrderSide::Buy, 101, 2)).unwrap();
+        assert!(execution.fills.is_empty());
+        assert_eq!(resting_orders(&orderbook, OrderSide::Buy, 101).len(), 1);
+
+        let mut ioc = limit_order("user2", OrderSide::Buy, 101, 2);
+        ioc.time_in_force = TimeInForce::Ioc;
+        assert_eq!(orderbook.add_order(&mut ioc).err(), Some(RejectReason::NotAllowedInAuction));
+    }
+
+    fn pro_rata_book(top_priority: bool, min_allocation: i64) -> Orderbook {
+        Orderbook::with_spec("TEST_MARKET".to_string(), MarketSpec {
+            step_size: Decimal::from_int(1),
+            allocation: Allocation::ProRata { top_priority, min_allocation: Decimal::from_int(min_allocation) },
+            ..MarketSpec::default()
+        })
+    }
+
+    /// Rests one sell order at 100 per quantity and returns their ids.
+    fn rest_asks(orderbook: &mut Orderbook, quantities: &[i64]) -> Vec<String> {
+        quantities.iter().enumerate().map(|(i, &quantity)| {
+            let mut order = limit_order(&format!("maker{}", i), OrderSide::Sell, 100, quantity);
+            orderbook.add_order(&mut order).unwrap();
+            order.order_id
+        }).collect()
+    }
+
+    fn allocations(execution: &Execution, order_ids: &[String]) -> Vec<i64> {
+        order_ids.iter().map(|id| {
+            let qty: Decimal = execution.fills.iter().filter(|f| f.marker_order_id == *id).map(|f| f.qty).sum();
+            qty.to_string().parse().unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn test_pro_rata_shares_fill_by_size() {
+        let mut orderbook = pro_rata_book(false, 0);
+        let makers = rest_asks(&mut orderbook, &[10, 30, 60]);
+
+        let execution = orderbook.add_order(&mut limit_order("taker", OrderSide::Buy, 100, 50)).unwrap();
+        assert_eq!(execution.executed_qty, Decimal::from_int(50));
+        assert_eq!(allocations(&execution, &makers), vec![5, 15, 30]);
+        // Fills are reported in time priority
+        assert_eq!(execution.fills[0].marker_order_id, makers[0]);
+        assert_eq!(resting_orders(&orderbook, OrderSide::Sell, 100).len(), 3);
+    }
+
+    #[test]
+    fn test_pro_rata_shares_large_quantities_without_overflow() {
+        let mut orderbook = pro_rata_book(false, 0);
+        let makers = rest_asks(&mut orderbook, &[100_000_000_000, 300_000_000_000]);
+
+        let execution = orderbook.add_order(&mut limit_order("taker", OrderSide::Buy, 100, 200_000_000_000)).unwrap();
+        assert_eq!(allocations(&execution, &makers), vec![50_000_000_000, 150_000_000_000]);
+    }
+
+    #[test]
+    fn test_pro_rata_rounding_remainder_goes_in_time_priority() {
+        // 7 * 5 / 15 rounds down to 2 each; the 1 left over goes to the oldest
+        let mut orderbook = pro_rata_book(false, 0);
+        let makers = rest_asks(&mut orderbook, &[5, 5, 5]);
+        let execution = orderbook.add_order(&mut limit_order("taker", OrderSide::Buy, 100, 7)).unwrap();
+        assert_eq!(allocations(&execution, &makers), vec![3, 2, 2]);
+
+        // Every share rounds down to zero, so time priority decides it all
+        let mut orderbook = pro_rata_book(false, 0);
+        let makers = rest_asks(&mut orderbook, &[1, 1, 1]);
+        let execution = orderbook.add_order(&mut limit_order("taker", OrderSide::Buy, 100, 2)).unwrap();
+        assert_eq!(allocations(&execution, &makers), vec![1, 1, 0]);
+        assert_eq!(resting_orders(&orderbook, OrderSide::Sell, 100).len(), 1);
+    }
+
+    #[test]
+    fn test_pro_rata_top_priority_fills_oldest_first() {
+        let mut orderbook = pro_rata_book(true, 0);
+        let makers = rest_asks(&mut orderbook, &[10, 10, 20]);
+
+        // The oldest takes 10; the other 10 splits 3.33 / 6.66, rounded down
+        // to 3 / 6 with the 1 left over going to the older of the two
+        let execution = orderbook.add_order(&mut limit_order("taker", OrderSide::Buy, 100, 20)).unwrap();
+        assert_eq!(allocations(&execution, &makers), vec![10, 4, 6]);
+        assert!(orderbook.get_order(&makers[0]).is_none());
+    }
+
+    #[test]
+    fn test_pro_rata_min_allocation_drops_small_shares() {
+        let mut orderbook = pro_rata_book(false, 2);
+        let makers = rest_asks(&mut orderbook, &[18, 2]);
+
+        // The small order's share of 1 is below the minimum; the older order
+        // picks it up as the remainder
+        let execution = orderbook.add_order(&mut limit_order("taker", OrderSide::Buy, 100, 10)).unwrap();
+        assert_eq!(allocations(&execution, &makers), vec![10, 0]);
+    }
+
+    #[test]
+    fn test_pro_rata_sweeps_levels_and_refreshes_icebergs() {
+        let mut orderbook = pro_rata_book(false, 0);
+        let mut iceberg = limit_order("maker0", OrderSide::Sell, 100, 10);
+        iceberg.iceberg = Some(Iceberg { display_quantity: Decimal::from_int(2), displayed: Decimal::from_int(2) });
+        orderbook.add_order(&mut iceberg).unwrap();
+        let plain = rest_asks(&mut orderbook, &[2]);
+        orderbook.add_order(&mut limit_order("maker2", OrderSide::Sell, 101, 5)).unwrap();
+
+        // The iceberg's hidden reserve at 100 is shared out tranche by
+        // tranche before anything trades at 101
+        let execution = orderbook.add_order(&mut limit_order("taker", OrderSide::Buy, 101, 7)).unwrap();
+        assert_eq!(allocations(&execution, &[iceberg.order_id.clone(), plain[0].clone()]), vec![5, 2]);
+        assert_eq!(orderbook.get_order(&iceberg.order_id).unwrap().displayed_quantity(), Decimal::from_int(1));
+
+        let execution = orderbook.add_order(&mut limit_order("taker", OrderSide::Buy, 101, 6)).unwrap();
+        assert_eq!(execution.executed_qty, Decimal::from_int(6));
+        assert!(orderbook.get_order(&iceberg.order_id).is_none());
+        assert_eq!(resting_orders(&orderbook, OrderSide::Sell, 101)[0].filled, Decimal::from_int(1));
+        assert_not_crossed(&orderbook);
+    }
+
+    #[test]
+    fn test_pro_rata_applies_self_trade_prevention_first() {
+        let mut orderbook = pro_rata_book(false, 0);
+        let makers = rest_asks(&mut orderbook, &[10, 10]);
+        let mut own = limit_order("taker", OrderSide::Sell, 100, 5);
+        orderbook.add_order(&mut own).unwrap();
+
+        // Cancel-newest kills the taker as soon as its own order is at the
+        // level, before anything is shared out; the estimate agrees
+        let mut fok = limit_order("taker", OrderSide::Buy, 100, 5);
+        fok.time_in_force = TimeInForce::Fok;
+        assert_eq!(orderbook.estimate_fill(&fok).0, Decimal::ZERO);
+        let mut taker = limit_order("taker", OrderSide::Buy, 100, 10);
+        let execution = orderbook.add_order(&mut taker).unwrap();
+        assert!(execution.fills.is_empty());
+        assert_eq!(execution.self_trade_cancelled_qty, Decimal::from_int(10));
+
+        let mut taker = limit_order("taker", OrderSide::Buy, 100, 10);
+        taker.self_trade_prevention = SelfTradePrevention::CancelOldest;
+        assert_eq!(orderbook.estimate_fill(&taker).0, Decimal::from_int(10));
+        let execution = orderbook.add_order(&mut taker).unwrap();
+        assert_eq!(execution.cancelled_makers.len(), 1);
+        assert_eq!(allocations(&execution, &makers), vec![5, 5]);
+    }
 }
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use crate::redis::redis_manager::OrderSide;
+use crate::trade::decimal::Decimal;
+use crate::trade::orderbook::Order;
+use std::collections::BTreeMap;
+
+/// Stop orders of one market waiting for the last trade price to reach their
+/// stop price. Buy stops fire when the price rises to the stop, sell stops
+/// when it falls to it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TriggerBook {
+    buy_stops: BTreeMap<Decimal, Vec<Order>>,
+    sell_stops: BTreeMap<Decimal, Vec<Order>>,
+}
+
+impl TriggerBook {
+    pub fn add(&mut self, order: Order) {
+        let stop_price = order.stop_price.expect("stop order without a stop price");
+        let stops = match order.side {
+            OrderSide::Buy => &mut self.buy_stops,
+            OrderSide::Sell => &mut self.sell_stops,
+        };
+        stops.entry(stop_price).or_default().push(order);
+    }
+
+    /// Removes and returns every stop order triggered by a trade at `last_price`,
+    /// in stop price order and then in the order they were placed.
+    pub fn take_triggered(&mut self, last_price: Decimal) -> Vec<Order> {
+        let mut triggered = Vec::new();
+
+        // Buy stops at or below the last price fire, lowest first
+        let pending_buys = self.buy_stops.split_off(&(last_price + Decimal::EPSILON));
+        let fired_buys = std::mem::replace(&mut self.buy_stops, pending_buys);
+        triggered.extend(fired_buys.into_values().flatten());
+
+        // Sell stops at or above the last price fire, highest first
+        let fired_sells = self.sell_stops.split_off(&last_price);
+        triggered.extend(fired_sells.into_values().rev().flatten());
+
+        triggered
+    }
+
+    /// Removes `user_id`'s stop order `order_id`, if it is still waiting.
+    pub fn cancel(&mut self, order_id: &str, user_id: &str) -> Option<Order> {
+        for stops in [&mut self.buy_stops, &mut self.sell_stops] {
+            let found = stops.iter().find_map(|(price, orders)| {
+                orders.iter()
+                    .position(|o| o.order_id == order_id && o.user_id == user_id)
+                    .map(|index| (*price, index))
+            });
+            if let Some((price, index)) = found {
+                let orders = stops.get_mut(&price).unwrap();
+                let order = orders.remove(index);
+                if orders.is_empty() {
+                    stops.remove(&price);
+                }
+                return Some(order);
+            }
+        }
+        None
+    }
+
+    pub fn get_order(&self, order_id: &str) -> Option<&Order> {
+        self.buy_stops.values()
+            .chain(self.sell_stops.values())
+            .flatten()
+            .find(|o| o.order_id == order_id)
+    }
+
+    pub fn get_open_orders(&self, user_id: &str) -> Vec<Order> {
+        self.buy_stops.values()
+            .chain(self.sell_stops.values())
+            .flatten()
+            .filter(|o| o.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::orderbook::{OrderType, SelfTradePrevention, TimeInForce};
+
+    fn stop_order(order_id: &str, side: OrderSide, stop_price: i64) -> Order {
+        Order {
+            order_id: order_id.to_string(),
+            user_id: "user1".to_string(),
+            price: Decimal::ZERO,
+            quantity: Decimal::from_int(1),
+            filled: Decimal::ZERO,
+            side,
+            order_type: OrderType::StopMarket,
+            time_in_force: TimeInForce::Gtc,
+            post_only: None,
+            stop_price: Some(Decimal::from_int(stop_price)),
+            iceberg: None,
+            self_trade_prevention: SelfTradePrevention::CancelNewest,
+            expires_at: None,
+            client_order_id: None,
+        }
+    }
+
+    #[test]
+    fn test_take_triggered_by_direction() {
+        let mut trigger_book = TriggerBook::default();
+        trigger_book.add(stop_order("buy_105", OrderSide::Buy, 105));
+        trigger_book.add(stop_order("buy_110", OrderSide::Buy, 110));
+        trigger_book.add(stop_order("sell_95", OrderSide::Sell, 95));
+        trigger_book.add(stop_order("sell_90", OrderSide::Sell, 90));
+
+        assert!(trigger_book.take_triggered(Decimal::from_int(100)).is_empty());
+
+        let triggered = trigger_book.take_triggered(Decimal::from_int(105));
+        assert_eq!(triggered.iter().map(|o| o.order_id.as_str()).collect::<Vec<_>>(), vec!["buy_105"]);
+
+        let triggered = trigger_book.take_triggered(Decimal::from_int(89));
+        assert_eq!(triggered.iter().map(|o| o.order_id.as_str()).collect::<Vec<_>>(), vec!["sell_95", "sell_90"]);
+
+        assert_eq!(trigger_book.get_open_orders("user1").len(), 1);
+    }
+
+    #[test]
+    fn test_cancel_only_own_orders() {
+        let mut trigger_book = TriggerBook::default();
+        trigger_book.add(stop_order("buy_105", OrderSide::Buy, 105));
+
+        assert!(trigger_book.cancel("buy_105", "user2").is_none());
+        assert!(trigger_book.cancel("buy_105", "user1").is_some());
+        assert!(trigger_book.get_open_orders("user1").is_empty());
+    }
+}
@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 use crate::redis::redis_manager::OrderSide;
 use crate::trade::orderbook::Order;
-use crate::trade::orderbook::Fill;
+use crate::trade::orderbook::{Fill, OrderStatus, OrderType, PostOnly, SelfTradePrevention, TimeInForce};
+use std::fmt;
+use crate::trade::decimal::Decimal;
+use crate::trade::asset::Asset;
+use crate::trade::market::{Market, MarketConfig, MarketStatus};
 
 
 #[derive(Debug, Deserialize)]
@@ -14,16 +18,34 @@ pub enum MessageFromApi {
     
     #[serde(rename = "CANCEL_ORDER")]
     CancelOrder {
-        order_id: String,
-        market: String,
-        user_id: String,
+        data: CancelOrderData,
+    },
+    
+    #[serde(rename = "AMEND_ORDER")]
+    AmendOrder {
+        data: AmendOrderData,
+    },
+
+    #[serde(rename = "CANCEL_ALL_ORDERS")]
+    CancelAllOrders {
+        data: CancelAllOrdersData,
     },
+
+    /// Arms, refreshes or (with a zero timeout) disarms the caller's dead
+    /// man's switch
+    #[serde(rename = "SET_DEAD_MAN_SWITCH")]
+    SetDeadManSwitch {
+        data: DeadManSwitchData,
+    },
+
+    /// Fires the caller's dead man's switch now, e.g. because the connection
+    /// that armed it dropped
+    #[serde(rename = "FIRE_DEAD_MAN_SWITCH")]
+    FireDeadManSwitch,
     
     #[serde(rename = "ON_RAMP")]
     OnRamp {
-        amount: String,
-        user_id: String,
-        txn_id: String,
+        data: OnRampData,
     },
     
     #[serde(rename = "GET_DEPTH")]
@@ -35,6 +57,92 @@ pub enum MessageFromApi {
     GetOpenOrders {
         data: GetOpenOrdersData,
     },
+
+    #[serde(rename = "GET_ORDER")]
+    GetOrder {
+        data: GetOrderData,
+    },
+
+    /// Adds an asset to the registry so markets can trade it
+    #[serde(rename = "LIST_ASSET")]
+    ListAsset {
+        data: Asset,
+    },
+
+    /// Lists a new market or updates the rules and status of a listed one
+    #[serde(rename = "LIST_MARKET")]
+    ListMarket {
+        data: Box<MarketConfig>,
+    },
+
+    #[serde(rename = "SET_MARKET_STATUS")]
+    SetMarketStatus {
+        data: SetMarketStatusData,
+    },
+}
+
+/// Identifies one of the caller's orders by the engine's order id or by the
+/// client order id it was placed with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelOrderData {
+    #[serde(default)]
+    pub order_id: Option<String>,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    pub market: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetOrderData {
+    #[serde(default)]
+    pub order_id: Option<String>,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    pub market: String,
+}
+
+/// Which of the caller's open orders to cancel; omitted filters match
+/// every market or both sides.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelAllOrdersData {
+    #[serde(default)]
+    pub market: Option<String>,
+    #[serde(default)]
+    pub side: Option<OrderSide>,
+}
+
+/// Cancel all of the caller's orders unless the switch is set again within
+/// `timeout_ms`; `0` disarms it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadManSwitchData {
+    pub timeout_ms: u64,
+}
+
+/// New price and/or total quantity for a resting order; omitted fields keep
+/// their current value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmendOrderData {
+    pub order_id: String,
+    pub market: String,
+    #[serde(default)]
+    pub price: Option<String>,
+    #[serde(default)]
+    pub quantity: Option<String>,
+}
+
+/// Credits `amount` of `asset` to a user's available balance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnRampData {
+    pub amount: String,
+    pub asset: String,
+    pub user_id: String,
+    pub txn_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetMarketStatusData {
+    pub market: String,
+    pub status: MarketStatus,
 }
 
 #[derive(Debug, Deserialize)]
@@ -59,15 +167,38 @@ pub enum MessageToApi {
     #[serde(rename = "ORDER_PLACED")]
     OrderPlaced {
         order_id: String,
-        executed_qty: f64,
+        status: OrderStatus,
+        executed_qty: Decimal,
+        cancelled_qty: Decimal,
         fills: Vec<Fill>,
     },
 
     #[serde(rename = "ORDER_CANCELLED")]
     OrderCancelled {
         order_id: String,
-        executed_qty: f64,
-        remaining_qty: f64,
+        executed_qty: Decimal,
+        remaining_qty: Decimal,
+    },
+
+    #[serde(rename = "ORDER_AMENDED")]
+    OrderAmended {
+        payload: OrderAmendedPayload,
+    },
+
+    /// Ids of the orders removed by a mass cancel
+    #[serde(rename = "ORDERS_CANCELLED")]
+    OrdersCancelled {
+        payload: Vec<String>,
+    },
+
+    #[serde(rename = "DEAD_MAN_SWITCH")]
+    DeadManSwitch {
+        payload: DeadManSwitchPayload,
+    },
+
+    #[serde(rename = "ORDER_REJECTED")]
+    OrderRejected {
+        payload: OrderRejectedPayload,
     },
 
     #[serde(rename = "OPEN_ORDERS")]
@@ -75,12 +206,141 @@ pub enum MessageToApi {
         payload: Vec<Order>,
     },
 
+    #[serde(rename = "ORDER")]
+    Order {
+        payload: Order,
+    },
+
+    #[serde(rename = "ASSET_LISTED")]
+    AssetListed {
+        payload: Asset,
+    },
+
+    #[serde(rename = "MARKET_LISTED")]
+    MarketListed {
+        payload: Box<MarketConfig>,
+    },
+
+    #[serde(rename = "MARKET_STATUS")]
+    MarketStatusChanged {
+        payload: Market,
+    },
+
     #[serde(rename = "ERROR")]
     Error {
         message: String,
     },
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderAmendedPayload {
+    pub order_id: String,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub status: OrderStatus,
+    /// Total executed so far, including fills from before the amend
+    pub executed_qty: Decimal,
+    /// Fills caused by the amend itself
+    pub fills: Vec<Fill>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadManSwitchPayload {
+    pub timeout_ms: u64,
+    /// When the caller's orders get cancelled (ms), `None` once disarmed
+    pub cancel_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRejectedPayload {
+    pub reason: RejectReason,
+    pub message: String,
+}
+
+/// Why the engine refused an order, so clients can tell e.g. a post-only
+/// rejection apart from a funding problem.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RejectReason {
+    UnknownMarket,
+    OrderNotFound,
+    MissingPrice,
+    InvalidPrice,
+    MissingStopPrice,
+    InvalidStopPrice,
+    InvalidQuantity,
+    InvalidTickSize,
+    InvalidStepSize,
+    PricePrecisionExceeded,
+    QuantityBelowMinimum,
+    QuantityAboveMaximum,
+    NotionalBelowMinimum,
+    NotionalAboveMaximum,
+    InvalidDisplayQuantity,
+    InsufficientFunds,
+    PostOnlyWouldCross,
+    MarketNotTrading,
+    MarketHalted,
+    PriceOutsideBand,
+    NotAllowedInAuction,
+    InvalidMarket,
+    MarketAssetsChanged,
+    InvalidExpiry,
+    InvalidClientOrderId,
+    UnknownAsset,
+    InvalidAsset,
+    AssetPrecisionExceeded,
+    InvalidAmount,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            RejectReason::UnknownMarket => "No orderbook found for market",
+            RejectReason::OrderNotFound => "No open order found with this id",
+            RejectReason::MissingPrice => "Price is required for limit orders",
+            RejectReason::InvalidPrice => "Invalid price",
+            RejectReason::MissingStopPrice => "Stop price is required for stop orders",
+            RejectReason::InvalidStopPrice => "Invalid stop price",
+            RejectReason::InvalidQuantity => "Invalid quantity",
+            RejectReason::InvalidTickSize => "Price is not a multiple of the market's tick size",
+            RejectReason::InvalidStepSize => "Quantity is not a multiple of the market's step size",
+            RejectReason::PricePrecisionExceeded => "Price has more decimal places than the market allows",
+            RejectReason::QuantityBelowMinimum => "Quantity is below the market's minimum",
+            RejectReason::QuantityAboveMaximum => "Quantity is above the market's maximum",
+            RejectReason::NotionalBelowMinimum => "Order value is below the market's minimum notional",
+            RejectReason::NotionalAboveMaximum => "Order value is too large",
+            RejectReason::InvalidDisplayQuantity => "Display quantity must be positive and is only allowed on limit orders",
+            RejectReason::InsufficientFunds => "Insufficient funds",
+            RejectReason::PostOnlyWouldCross => "Post-only order would cross the book",
+            RejectReason::MarketNotTrading => "Market is not accepting new orders",
+            RejectReason::MarketHalted => "Market is halted",
+            RejectReason::PriceOutsideBand => "Price is too far from the market's reference price",
+            RejectReason::NotAllowedInAuction => "Only good-till-cancelled limit orders are accepted during an auction",
+            RejectReason::InvalidMarket => "Market needs a symbol, two distinct assets and positive tick and step sizes",
+            RejectReason::MarketAssetsChanged => "A listed market's base and quote assets cannot be changed",
+            RejectReason::InvalidExpiry => "Expiry must be in the future and is only allowed on orders that can rest",
+            RejectReason::InvalidClientOrderId => "Client order id must be 1 to 64 characters",
+            RejectReason::UnknownAsset => "No asset listed with this symbol",
+            RejectReason::InvalidAsset => "Asset needs a symbol and a precision of at most 8 decimals, which cannot change once listed",
+            RejectReason::AssetPrecisionExceeded => "Tick and step sizes cannot be finer than the quote and base asset precisions",
+            RejectReason::InvalidAmount => "Amount must be positive and within the asset's precision",
+        };
+        f.write_str(message)
+    }
+}
+
+impl From<RejectReason> for MessageToApi {
+    fn from(reason: RejectReason) -> Self {
+        MessageToApi::OrderRejected {
+            payload: OrderRejectedPayload {
+                reason,
+                message: reason.to_string(),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepthPayload {
     pub market: String,
@@ -91,7 +351,28 @@ pub struct DepthPayload {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateOrderData {
     pub market: String,
-    pub price: String,
+    #[serde(default)]
+    pub price: Option<String>,
     pub quantity: String,
     pub side: OrderSide,
+    #[serde(default)]
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub post_only: Option<PostOnly>,
+    #[serde(default)]
+    pub stop_price: Option<String>,
+    /// Visible tranche size; makes the order an iceberg
+    #[serde(default)]
+    pub display_quantity: Option<String>,
+    #[serde(default)]
+    pub self_trade_prevention: SelfTradePrevention,
+    /// Good-till-date: cancel whatever is still open at this time (ms)
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Makes placement idempotent: a retry with the same id gets the
+    /// original outcome back instead of placing a second order
+    #[serde(default)]
+    pub client_order_id: Option<String>,
 }
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use crate::trade::market::MarketStatus;
+use crate::trade::orderbook::OrderStatus;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TickerData {
@@ -38,12 +40,50 @@ pub struct TradeData {
     pub s: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusData {
+    pub e: String, // Will always be "status"
+    pub s: String,
+    pub status: MarketStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuctionData {
+    pub e: String, // Will always be "auction"
+    pub s: String,
+    /// Indicative clearing price and volume, absent while the book does not cross
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    /// When the auction ends, in milliseconds
+    pub end: i64,
+}
+
+/// Change to one of a user's orders that did not come from a request of
+/// theirs, published on `order@{user_id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderUpdateData {
+    pub e: String, // Will always be "order"
+    pub s: String,
+    /// Order id
+    pub i: String,
+    #[serde(rename = "X")]
+    pub status: OrderStatus,
+    /// Total quantity and executed quantity
+    pub q: String,
+    pub z: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum WsMessageData {
     Ticker(TickerData),
     Depth(DepthData),
     Trade(TradeData),
+    Status(StatusData),
+    Auction(AuctionData),
+    OrderUpdate(OrderUpdateData),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
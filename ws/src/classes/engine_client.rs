@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+use once_cell::sync::Lazy;
+use redis::{Client, Commands, RedisResult};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+
+/// Sends requests to the engine on behalf of an authenticated connection,
+/// through the same `messages` queue the API uses.
+pub struct EngineClient {
+    redis_client: Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+}
+
+static INSTANCE: Lazy<Arc<Mutex<EngineClient>>> = Lazy::new(|| {
+    Arc::new(Mutex::new(EngineClient::new()))
+});
+
+impl EngineClient {
+    fn new() -> Self {
+        let redis_url = std::env::var("REDIS_1_URL")
+            .unwrap_or_else(|_| "redis://localhost:6379".to_string());
+
+        let redis_client = Client::open(redis_url)
+            .expect("Failed to create Redis client");
+
+        Self { redis_client }
+    }
+
+    pub fn get_instance() -> Arc<Mutex<EngineClient>> {
+        Arc::clone(&INSTANCE)
+    }
+
+    /// Id of the user the API issued `token` to, if it is valid.
+    pub fn authenticate(token: &str) -> Option<String> {
+        let secret = std::env::var("JWT_SECRET").ok()?;
+        let decoding_key = DecodingKey::from_secret(secret.as_bytes());
+        decode::<Claims>(token, &decoding_key, &Validation::default())
+            .ok()
+            .map(|data| data.claims.sub)
+    }
+
+    /// Queues `message` for the engine. Replies go to `client_id`, which
+    /// nobody listens on; the connection only cares about the side effects.
+    pub fn send(&self, client_id: &str, user_id: &str, message: Value) -> RedisResult<()> {
+        let wrapper = json!({
+            "client_id": client_id,
+            "user_id": user_id,
+            "message": message,
+        });
+        let mut conn = self.redis_client.get_connection()?;
+        conn.lpush("messages", wrapper.to_string())
+    }
+
+    pub fn set_dead_man_switch(&self, client_id: &str, user_id: &str, timeout_ms: u64) -> RedisResult<()> {
+        self.send(client_id, user_id, json!({
+            "type": "SET_DEAD_MAN_SWITCH",
+            "data": { "timeout_ms": timeout_ms },
+        }))
+    }
+
+    pub fn fire_dead_man_switch(&self, client_id: &str, user_id: &str) -> RedisResult<()> {
+        self.send(client_id, user_id, json!({
+            "type": "FIRE_DEAD_MAN_SWITCH",
+        }))
+    }
+}
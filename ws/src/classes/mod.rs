@@ -1,3 +1,4 @@
 pub mod user;
+pub mod engine_client;
 pub mod subscription_manager;
 pub mod user_manager;
\ No newline at end of file
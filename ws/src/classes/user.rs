@@ -2,11 +2,15 @@ use tokio::sync::mpsc::{Sender, Receiver};
 use warp::ws::{Message, WebSocket};
 use serde_json::{json, Value};
 use crate::classes::subscription_manager::SubscriptionManager;
+use crate::classes::engine_client::EngineClient;
 
 pub struct User {
     pub id: String,
     pub ws: Sender<Message>,
     subscriptions: Vec<String>,
+    /// Account whose dead man's switch this connection keeps alive; its
+    /// orders are cancelled when the connection drops
+    pub dead_man_switch: Option<String>,
 }
 
 impl User {
@@ -15,6 +19,7 @@ impl User {
             id,
             ws,
             subscriptions: Vec::new(),
+            dead_man_switch: None,
         }
     }
 
@@ -59,6 +64,29 @@ impl User {
                         }
                     }
                 }
+                Some("DEAD_MAN_SWITCH") => {
+                    // params: [token, timeout_ms]; a zero timeout disarms it
+                    let token = parsed["params"][0].as_str();
+                    let timeout_ms = parsed["params"][1].as_u64();
+                    let Some((user_id, timeout_ms)) = token
+                        .and_then(EngineClient::authenticate)
+                        .zip(timeout_ms)
+                    else {
+                        self.emit(json!({ "method": "DEAD_MAN_SWITCH", "error": "Invalid token or timeout" })).await;
+                        return;
+                    };
+
+                    let sent = match EngineClient::get_instance().lock() {
+                        Ok(client) => client.set_dead_man_switch(&self.id, &user_id, timeout_ms).is_ok(),
+                        Err(_) => false,
+                    };
+                    if !sent {
+                        self.emit(json!({ "method": "DEAD_MAN_SWITCH", "error": "Engine unavailable" })).await;
+                        return;
+                    }
+                    self.dead_man_switch = (timeout_ms > 0).then_some(user_id);
+                    self.emit(json!({ "method": "DEAD_MAN_SWITCH", "result": { "timeout_ms": timeout_ms } })).await;
+                }
                 _ => println!("Unknown method"),
             }
         }
@@ -6,7 +6,7 @@ use once_cell::sync::Lazy;
 use warp::ws::WebSocket;
 use futures_util::{StreamExt, SinkExt};
 use rand::{Rng, distributions::Alphanumeric};
-use crate::classes::{user::User, subscription_manager::SubscriptionManager};
+use crate::classes::{user::User, subscription_manager::SubscriptionManager, engine_client::EngineClient};
 use log::info;
 
 pub struct UserManager {
@@ -58,6 +58,7 @@ impl UserManager {
                     Err(_) => break,
                 }
             }
+            UserManager::get_instance().lock().await.remove_user(&id_clone).await;
         });
 
         // Handle outgoing messages
@@ -91,7 +92,28 @@ impl UserManager {
 
     pub async fn remove_user(&mut self, id: &str) {
         info!("Removing user: {}", id);
-        if let Some(user) = self.users.lock().await.remove(id) {
+        let removed = {
+            let mut users = self.users.lock().await;
+            users.remove(id).map(|user| {
+                // Other connections of the same user that armed the switch
+                // keep it alive; only the last one to go cancels
+                let fire = user.dead_man_switch.clone().filter(|user_id| {
+                    !users.values().any(|other| other.dead_man_switch.as_ref() == Some(user_id))
+                });
+                (user, fire)
+            })
+        };
+        if let Some((user, fire)) = removed {
+            // Cancel on disconnect: don't wait for the switch to time out
+            if let Some(user_id) = &fire {
+                info!("Connection {} dropped, cancelling orders of {}", id, user_id);
+                if let Ok(client) = EngineClient::get_instance().lock() {
+                    if let Err(e) = client.fire_dead_man_switch(id, user_id) {
+                        info!("Failed to cancel orders of {}: {}", user_id, e);
+                    }
+                }
+            }
+
             // First unsubscribe from all channels
             if let Ok(mut sub_manager) = SubscriptionManager::get_instance().lock() {
                 for channel in user.get_subscriptions() {